@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+
+/// Annual borrow rates are charged on a 360 day year, as is usual for stock loans
+pub const BORROW_DAYS_PER_YEAR: f64 = 360.0;
+
+/// Errors that can occur while loading a borrow table
+#[derive(Debug, thiserror::Error)]
+pub enum BorrowError {
+    #[error("Failed to read borrow table {path}: {source}")]
+    Read { path: PathBuf, source: csv::Error },
+    #[error("Invalid borrow rate {rate} for {ticker} in {path}")]
+    InvalidRate { path: PathBuf, ticker: String, rate: f64 },
+}
+
+/// One row of a borrow table
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct BorrowEntry {
+    /// Annual borrow fee in percent (e.g. 35.0 for a hard-to-borrow name)
+    pub rate: f64,
+    /// Whether a locate can be found; shorts are rejected when false
+    #[serde(default = "available_default")]
+    pub available: bool,
+}
+
+fn available_default() -> bool {
+    true
+}
+
+/// Short borrow settings and the per-ticker rate table
+///
+/// The table is a CSV file with `ticker,rate[,available]` columns, loaded when
+/// the config is. Tickers not in the table borrow at `default_rate` if
+/// `allow_unlisted` is set and cannot be shorted otherwise.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BorrowConfig {
+    /// CSV file with the hard-to-borrow list
+    pub table: Option<PathBuf>,
+    /// Annual rate in percent for tickers not in the table
+    pub default_rate: f64,
+    /// Allow shorting tickers that are not in the table
+    pub allow_unlisted: bool,
+    #[serde(skip)]
+    pub entries: HashMap<String, BorrowEntry>,
+}
+
+impl Default for BorrowConfig {
+    fn default() -> Self {
+        Self {
+            table: None,
+            default_rate: 0.0,
+            allow_unlisted: true,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+/// Why a short entry was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocateError {
+    /// The table marks the ticker as unavailable
+    Unavailable(String),
+    /// The ticker is not in the table and `allow_unlisted` is off
+    NotListed(String),
+}
+
+impl std::fmt::Display for LocateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocateError::Unavailable(ticker) => write!(f, "no locate available for {}", ticker),
+            LocateError::NotListed(ticker) => write!(f, "{} is not in the borrow table", ticker),
+        }
+    }
+}
+
+impl BorrowConfig {
+    /// Load `table` into `entries`, resolving a relative path against `base_dir`
+    pub fn load_table(&mut self, base_dir: Option<&Path>) -> Result<(), BorrowError> {
+        let Some(table) = &self.table else {
+            return Ok(());
+        };
+        let path = match base_dir {
+            Some(dir) if table.is_relative() => dir.join(table),
+            _ => table.clone(),
+        };
+        self.entries = read_table(&path)?;
+        Ok(())
+    }
+
+    /// Annual rate for shorting `ticker`, or why it can't be shorted
+    pub fn locate(&self, ticker: &str) -> Result<f64, LocateError> {
+        match self.entries.get(ticker) {
+            Some(entry) if entry.available => Ok(entry.rate),
+            Some(_) => Err(LocateError::Unavailable(ticker.to_string())),
+            None if self.allow_unlisted => Ok(self.default_rate),
+            None => Err(LocateError::NotListed(ticker.to_string())),
+        }
+    }
+
+    /// Borrow cost of holding `notional` dollars short for `days` calendar days
+    pub fn borrow_cost(&self, ticker: &str, notional: f64, days: i64) -> f64 {
+        let rate = self.locate(ticker).unwrap_or(self.default_rate);
+        notional.abs() * rate / 100.0 / BORROW_DAYS_PER_YEAR * days as f64
+    }
+}
+
+fn read_table(path: &Path) -> Result<HashMap<String, BorrowEntry>, BorrowError> {
+    #[derive(Deserialize)]
+    struct Row {
+        ticker: String,
+        #[serde(flatten)]
+        entry: BorrowEntry,
+    }
+
+    let read_err = |source| BorrowError::Read { path: path.to_path_buf(), source };
+    let mut reader = csv::Reader::from_path(path).map_err(read_err)?;
+    let mut entries = HashMap::new();
+    for row in reader.deserialize() {
+        let row: Row = row.map_err(read_err)?;
+        if !(row.entry.rate.is_finite() && row.entry.rate >= 0.0) {
+            return Err(BorrowError::InvalidRate {
+                path: path.to_path_buf(),
+                ticker: row.ticker,
+                rate: row.entry.rate,
+            });
+        }
+        entries.insert(row.ticker, row.entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_table_and_locate() {
+        let dir = std::env::temp_dir().join(format!("borrow_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("htb.csv"), "ticker,rate,available\nGME,80.0,true\nAMC,0,false\n").unwrap();
+
+        let mut borrow = BorrowConfig {
+            table: Some(PathBuf::from("htb.csv")),
+            default_rate: 0.3,
+            ..BorrowConfig::default()
+        };
+        borrow.load_table(Some(&dir)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(borrow.locate("GME"), Ok(80.0));
+        assert_eq!(borrow.locate("AMC"), Err(LocateError::Unavailable("AMC".into())));
+        assert_eq!(borrow.locate("AAPL"), Ok(0.3));
+        borrow.allow_unlisted = false;
+        assert_eq!(borrow.locate("AAPL"), Err(LocateError::NotListed("AAPL".into())));
+
+        // $36,000 short at 80% for one day
+        assert!((borrow.borrow_cost("GME", 36_000.0, 1) - 80.0).abs() < 1e-9);
+    }
+}
@@ -1,21 +1,37 @@
 use std::collections::HashMap;
 use crate::types::ohlcv::Row;
 use crate::indicators::indicator::Indicator;
+use crate::config::MarketHours;
+use crate::position::order::{Order, OrderId};
 
 /// Context for a single ticker, holding its data and indicators
 pub struct TickerContext {
     pub ticker: String,
     pub indicators: HashMap<String, Box<dyn Indicator>>,
     pub latest_row: Option<Row>,
+    /// Session times of the engine's config, for indicators that need them (e.g. ACV)
+    pub market_hours: MarketHours,
+    /// Name of the indicator that supplies ATR for `OrderDistance::ATR` orders
+    pub atr_indicator: Option<String>,
+    /// Ids of the orders queued for each signal returned on this ticker's previous bar,
+    /// aligned with those signals: a group signal has one id per leg, and a rejected,
+    /// cancel or replace signal an empty entry
+    pub submitted_orders: Vec<Vec<OrderId>>,
+    /// This ticker's orders still working as of the previous bar, oldest first
+    pub working_orders: Vec<(OrderId, Order)>,
     // Could add history buffer here if needed
 }
 
 impl TickerContext {
-    pub fn new(ticker: String) -> Self {
+    pub fn new(ticker: String, market_hours: MarketHours) -> Self {
         Self {
             ticker,
             indicators: HashMap::new(),
             latest_row: None,
+            market_hours,
+            atr_indicator: None,
+            submitted_orders: Vec::new(),
+            working_orders: Vec::new(),
         }
     }
 
@@ -31,6 +47,16 @@ impl TickerContext {
         self.latest_row = Some(row.clone());
     }
 
+    /// Use the indicator registered as `name` (e.g. an `ATR`) for ATR-based order distances
+    pub fn set_atr_indicator(&mut self, name: &str) {
+        self.atr_indicator = Some(name.to_string());
+    }
+
+    /// Current ATR, if an ATR indicator is set and ready
+    pub fn atr(&self) -> Option<f64> {
+        self.atr_indicator.as_deref().and_then(|name| self.get_indicator(name))
+    }
+
     pub fn get_indicator(&self, name: &str) -> Option<f64> {
         self.indicators.get(name).and_then(|ind| ind.get())
     }
@@ -7,6 +7,7 @@ use crate::config::Config;
 use crate::types::log::TradeLog;
 
 pub struct BacktestEngine {
+    pub config: Config,
     pub tickers: HashMap<String, TickerContext>,
     pub portfolio: Portfolio,
     pub strategy_factory: Box<dyn Fn() -> Box<dyn Strategy>>,
@@ -16,10 +17,11 @@ pub struct BacktestEngine {
 }
 
 impl BacktestEngine {
-    pub fn new(_config: Config, strategy_factory: Box<dyn Fn() -> Box<dyn Strategy>>) -> Self {
+    pub fn new(config: Config, strategy_factory: Box<dyn Fn() -> Box<dyn Strategy>>) -> Self {
         Self {
+            portfolio: Portfolio::new(config.clone()),
+            config,
             tickers: HashMap::new(),
-            portfolio: Portfolio::new(),
             strategy_factory,
             strategies: HashMap::new(),
             trade_logs: Vec::new(),
@@ -29,12 +31,13 @@ impl BacktestEngine {
     pub fn process_row(&mut self, row: &Row) {
         let ticker = &row.ticker;
         
-        // 1. Update Price in Portfolio
+        // 1. Charge overnight borrow, then update Price in Portfolio
+        self.portfolio.accrue_borrow(row.timestamp);
         self.portfolio.update_prices(ticker, row.close);
 
         // 2. Get or Create Context & Strategy
         if !self.tickers.contains_key(ticker) {
-            let mut context = TickerContext::new(ticker.to_string());
+            let mut context = TickerContext::new(ticker.to_string(), self.config.market_hours.clone());
             let strategy = (self.strategy_factory)();
             strategy.setup(&mut context); // Register indicators
             
@@ -45,29 +48,69 @@ impl BacktestEngine {
         let context = self.tickers.get_mut(ticker).unwrap();
         let strategy = self.strategies.get_mut(ticker).unwrap();
 
-        // 3. Update Context (feeds data to indicators)
+        // 3. Update Context (feeds data to indicators and shows the strategy its orders)
         context.update(row);
+        context.working_orders = self.portfolio.working_orders(ticker);
 
         // 4. Run Strategy Logic
         let signals = strategy.generate_signals(context);
         
         // Capture indicator values for logging
         let indicator_values = context.get_indicator_values();
+        let atr = context.atr();
         let strategy_name = strategy.name().to_string();
 
         // 5. Process Signals (Create Pending Orders)
+        context.submitted_orders.clear();
         for signal in signals {
-            self.portfolio.process_signal(
+            let ids = self.portfolio.process_signal(
                 &signal, 
                 row.close, 
                 row.timestamp, 
+                atr,
                 &indicator_values, 
                 &strategy_name
             );
+            context.submitted_orders.push(ids);
         }
 
         // 6. Check & Execute Pending Orders
         let logs = self.portfolio.check_orders(row);
         self.trade_logs.extend(logs);
+        let liquidations = self.portfolio.check_margin(row.timestamp);
+        self.trade_logs.extend(liquidations);
+
+        // 7. Mark the account to market
+        self.portfolio.record_equity(row.timestamp);
+    }
+
+    /// Feed a stream of rows through the engine in order
+    ///
+    /// Stops at the first error and returns it; otherwise calls `finish` once the rows run
+    /// out and returns the number of rows processed.
+    /// Works with any fallible row source, e.g. `DataSource::rows` or `FrameRows`.
+    pub fn run<I, E>(&mut self, rows: I) -> Result<usize, E>
+    where
+        I: IntoIterator<Item = Result<Row, E>>,
+    {
+        let mut count = 0;
+        let mut last = None;
+        for row in rows {
+            let row = row?;
+            self.process_row(&row);
+            last = Some(row.timestamp);
+            count += 1;
+        }
+        if let Some(timestamp) = last {
+            self.finish(timestamp);
+        }
+        Ok(count)
+    }
+
+    /// Settle orders that wait for a later bar when there is none, after the last row
+    /// at `timestamp`: market-on-close orders fill at the last session close seen
+    pub fn finish(&mut self, timestamp: i64) {
+        let logs = self.portfolio.settle_close_auctions(timestamp);
+        self.trade_logs.extend(logs);
     }
 }
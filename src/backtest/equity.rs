@@ -0,0 +1,138 @@
+use std::path::Path;
+use chrono::DateTime;
+use serde::Serialize;
+
+/// Account state at one point in time, valued at the last seen prices
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EquitySnapshot {
+    pub timestamp: i64,
+    /// Cash (`Portfolio::cash`)
+    pub cash: f64,
+    /// Cash plus the market value of open positions
+    pub equity: f64,
+    /// Unrealized PnL of open positions
+    pub unrealized_pnl: f64,
+    /// Market value of long positions
+    pub long_exposure: f64,
+    /// Market value of short positions (positive)
+    pub short_exposure: f64,
+}
+
+impl EquitySnapshot {
+    /// Long plus short market value
+    pub fn gross_exposure(&self) -> f64 {
+        self.long_exposure + self.short_exposure
+    }
+}
+
+/// Mark-to-market equity series, one snapshot per bar timestamp
+#[derive(Debug, Clone, Default)]
+pub struct EquityCurve {
+    snapshots: Vec<EquitySnapshot>,
+}
+
+impl EquityCurve {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a snapshot, replacing the last one if it has the same timestamp
+    ///
+    /// Rows for different tickers share a bar timestamp, so only the state after
+    /// the last row of each bar is kept.
+    pub fn record(&mut self, snapshot: EquitySnapshot) {
+        match self.snapshots.last_mut() {
+            Some(last) if last.timestamp == snapshot.timestamp => *last = snapshot,
+            _ => self.snapshots.push(snapshot),
+        }
+    }
+
+    /// Add the change from `before` to `after` to every snapshot from `before.timestamp`
+    /// up to, not including, `until`
+    ///
+    /// Used when a fill is back-dated to an earlier bar, see `Portfolio::check_orders`:
+    /// the snapshots recorded since then, including those of other tickers' bars, get
+    /// the fill's effect on cash, equity and exposure.
+    pub fn restate(&mut self, before: &EquitySnapshot, after: &EquitySnapshot, until: i64) {
+        let restated = self.snapshots.iter_mut().filter(|s| s.timestamp >= before.timestamp && s.timestamp < until);
+        for snapshot in restated {
+            snapshot.cash += after.cash - before.cash;
+            snapshot.equity += after.equity - before.equity;
+            snapshot.unrealized_pnl += after.unrealized_pnl - before.unrealized_pnl;
+            snapshot.long_exposure += after.long_exposure - before.long_exposure;
+            snapshot.short_exposure += after.short_exposure - before.short_exposure;
+        }
+    }
+
+    /// One snapshot per bar
+    pub fn bars(&self) -> &[EquitySnapshot] {
+        &self.snapshots
+    }
+
+    /// The last snapshot of each calendar day
+    pub fn daily(&self) -> Vec<EquitySnapshot> {
+        let mut days: Vec<EquitySnapshot> = Vec::new();
+        for snapshot in &self.snapshots {
+            match days.last_mut() {
+                Some(last) if same_day(last.timestamp, snapshot.timestamp) => *last = *snapshot,
+                _ => days.push(*snapshot),
+            }
+        }
+        days
+    }
+
+    /// `(timestamp, equity)` pairs, as taken by `PerformanceReport::compute`
+    pub fn points(&self) -> Vec<(i64, f64)> {
+        self.snapshots.iter().map(|s| (s.timestamp, s.equity)).collect()
+    }
+
+    pub fn last(&self) -> Option<&EquitySnapshot> {
+        self.snapshots.last()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+fn same_day(a: i64, b: i64) -> bool {
+    DateTime::from_timestamp_nanos(a).date_naive() == DateTime::from_timestamp_nanos(b).date_naive()
+}
+
+/// Write equity snapshots to a CSV file, one row per snapshot
+pub fn write_csv(snapshots: &[EquitySnapshot], path: &Path) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for snapshot in snapshots {
+        writer.serialize(snapshot)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: i64, equity: f64) -> EquitySnapshot {
+        EquitySnapshot { timestamp, cash: equity, equity, unrealized_pnl: 0.0, long_exposure: 0.0, short_exposure: 0.0 }
+    }
+
+    #[test]
+    fn test_daily_keeps_last_snapshot_of_each_day() {
+        const HOUR: i64 = 3600 * 1_000_000_000;
+        let mut curve = EquityCurve::new();
+        for (i, equity) in [100.0, 101.0, 102.0].into_iter().enumerate() {
+            curve.record(at(10 * HOUR + i as i64 * HOUR, equity));
+        }
+        curve.record(at(34 * HOUR, 99.0));
+
+        assert_eq!(curve.bars().len(), 4);
+        let daily: Vec<f64> = curve.daily().iter().map(|s| s.equity).collect();
+        assert_eq!(daily, vec![102.0, 99.0]);
+
+        // A fill back-dated to 11:00 that cost 2 in fees, found on the next day's bar
+        curve.restate(&at(11 * HOUR, 50.0), &at(11 * HOUR, 48.0), 34 * HOUR);
+        let points: Vec<f64> = curve.points().iter().map(|p| p.1).collect();
+        assert_eq!(points, vec![100.0, 99.0, 100.0, 99.0]);
+    }
+}
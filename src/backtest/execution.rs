@@ -0,0 +1,194 @@
+use chrono::{DateTime, NaiveTime};
+use serde::Deserialize;
+use crate::config::MarketHours;
+use crate::position::order::OrderType;
+use crate::types::ohlcv::Row;
+
+/// When an order submitted on a bar can first fill, and at what price market orders fill
+///
+/// Written in config files as e.g. `timing = "NextBarOpen"` or `timing = { LatencyBars = 2 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ExecutionTiming {
+    /// Fill on the signal bar, market orders at its close (look-ahead: the signal saw that close)
+    #[default]
+    SameBarClose,
+    /// Fill from the next bar on, market orders at its open
+    NextBarOpen,
+    /// Fill from the next bar on, market orders at its typical price `(high + low + close) / 3`,
+    /// a VWAP proxy since bars carry no VWAP
+    NextBarTypical,
+    /// Fill from the N-th bar after the signal on, market orders at that bar's open
+    LatencyBars(usize),
+}
+
+impl ExecutionTiming {
+    /// Bars of the order's ticker to skip before the order can fill
+    pub fn delay(&self) -> usize {
+        match self {
+            ExecutionTiming::SameBarClose => 0,
+            ExecutionTiming::NextBarOpen | ExecutionTiming::NextBarTypical => 1,
+            ExecutionTiming::LatencyBars(n) => *n,
+        }
+    }
+
+    /// Price a market order fills at on `row`
+    pub fn market_price(&self, row: &Row) -> f64 {
+        match self {
+            ExecutionTiming::SameBarClose => row.close,
+            ExecutionTiming::NextBarOpen => row.open,
+            ExecutionTiming::NextBarTypical => (row.high + row.low + row.close) / 3.0,
+            // A zero-bar latency is the same bar, which only has its close left
+            ExecutionTiming::LatencyBars(0) => row.close,
+            ExecutionTiming::LatencyBars(_) => row.open,
+        }
+    }
+}
+
+/// Assumed order in which a bar visited its high and low
+///
+/// Bars don't say whether the high or the low came first, which decides e.g. whether a
+/// stop-loss or a profit target on the same bar filled. Fills that depend on this
+/// are counted in `Portfolio::ambiguous_fills`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum IntrabarPath {
+    /// Open, high, low, close
+    OpenHighLowClose,
+    /// Open, low, high, close
+    OpenLowHighClose,
+    /// Whichever extreme is closer to the open first
+    #[default]
+    NearestExtreme,
+    /// The extreme against the trader's position first: the low for longs, the high for shorts
+    WorstCase,
+}
+
+impl IntrabarPath {
+    /// Whether the bar reached its high before its low; `long` is the side of the
+    /// position the order opens or closes
+    pub fn high_first(&self, row: &Row, long: bool) -> bool {
+        match self {
+            IntrabarPath::OpenHighLowClose => true,
+            IntrabarPath::OpenLowHighClose => false,
+            IntrabarPath::NearestExtreme => row.high - row.open <= row.open - row.low,
+            IntrabarPath::WorstCase => !long,
+        }
+    }
+
+    /// Leg of the bar on which `price` was first reached: 0 at the open, then 1 and 2
+    /// for the legs to the first and second extreme
+    pub fn leg(&self, row: &Row, price: f64, long: bool) -> u8 {
+        if price == row.open {
+            0
+        } else if (price > row.open) == self.high_first(row, long) {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+/// How orders are filled
+///
+/// Limits fill at the limit price, or at the open when the bar gaps through them.
+/// Stops trigger at the stop price, or at the open when the bar gaps through them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutionConfig {
+    pub timing: ExecutionTiming,
+    /// Minimum price increment
+    pub tick_size: f64,
+    /// Only fill limits when price trades through them by a tick, not when it just touches
+    pub trade_through: bool,
+    /// Order of the high and low within a bar
+    pub intrabar_path: IntrabarPath,
+    /// Largest fraction of a bar's volume one order can take (0.1 = 10%); the rest
+    /// carries over to later bars. None fills any size at once.
+    pub max_participation: Option<f64>,
+    /// Submission cutoffs for market-on-open and market-on-close orders
+    pub auction: AuctionConfig,
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            timing: ExecutionTiming::default(),
+            tick_size: 0.01,
+            trade_through: false,
+            intrabar_path: IntrabarPath::default(),
+            max_participation: None,
+            auction: AuctionConfig::default(),
+        }
+    }
+}
+
+impl ExecutionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.tick_size.is_finite() && self.tick_size > 0.0) {
+            return Err(format!("execution.tick_size must be > 0, got {}", self.tick_size));
+        }
+        match self.max_participation {
+            Some(rate) if !(rate.is_finite() && rate > 0.0 && rate <= 1.0) => {
+                Err(format!("execution.max_participation must be in (0, 1], got {}", rate))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Most shares one order can fill on `row`, None if fills are not volume constrained
+    pub fn fill_capacity(&self, row: &Row) -> Option<i64> {
+        self.max_participation.map(|rate| (row.volume as f64 * rate).floor() as i64)
+    }
+}
+
+/// Submission cutoffs for auction orders, as exchange times of day ("HH:MM:SS" in config files)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuctionConfig {
+    /// Market-on-open orders submitted after this and before the open are rejected;
+    /// ones submitted after the open go to the next day's opening auction
+    pub open_cutoff: NaiveTime,
+    /// Market-on-close orders submitted after this are rejected
+    pub close_cutoff: NaiveTime,
+}
+
+impl Default for AuctionConfig {
+    /// The NYSE cutoffs
+    fn default() -> Self {
+        Self {
+            open_cutoff: NaiveTime::from_hms_opt(9, 28, 0).unwrap(),
+            close_cutoff: NaiveTime::from_hms_opt(15, 50, 0).unwrap(),
+        }
+    }
+}
+
+impl AuctionConfig {
+    pub fn validate(&self, market_hours: &MarketHours) -> Result<(), String> {
+        if self.open_cutoff > market_hours.market_open {
+            return Err(format!(
+                "execution.auction.open_cutoff ({}) is after market_open ({})",
+                self.open_cutoff, market_hours.market_open
+            ));
+        }
+        if self.close_cutoff > market_hours.market_close {
+            return Err(format!(
+                "execution.auction.close_cutoff ({}) is after market_close ({})",
+                self.close_cutoff, market_hours.market_close
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether an order submitted at `timestamp` makes its auction's cutoff
+    pub fn check_submission(&self, order_type: &OrderType, timestamp: i64, market_hours: &MarketHours) -> Result<(), String> {
+        let time = DateTime::from_timestamp_nanos(timestamp).time();
+        match order_type {
+            OrderType::AuctionOpen(_) if time > self.open_cutoff && time < market_hours.market_open => Err(format!(
+                "market-on-open order submitted at {} after the {} cutoff", time, self.open_cutoff
+            )),
+            OrderType::AuctionClose(_) if time > self.close_cutoff => Err(format!(
+                "market-on-close order submitted at {} after the {} cutoff", time, self.close_cutoff
+            )),
+            _ => Ok(()),
+        }
+    }
+}
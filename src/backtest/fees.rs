@@ -0,0 +1,285 @@
+use chrono::{DateTime, Datelike};
+use serde::{Deserialize, Serialize};
+
+/// Broker commission schedule
+///
+/// Written in config files as e.g. `commission = { PerShare = { rate = 0.005, min = 1.0, max = 50.0 } }`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+pub enum CommissionModel {
+    /// No commission
+    #[default]
+    None,
+    /// Dollars per share, with the order's total commission clamped to `[min, max]`
+    PerShare {
+        rate: f64,
+        #[serde(default)]
+        min: f64,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    /// Flat dollars per order, charged on its first fill
+    PerTrade(f64),
+    /// Percent of the order's notional value (0.1 = 0.1%)
+    PercentOfNotional(f64),
+    /// Per-share rate picked by the shares already traded this calendar month, at least `min` per order
+    Tiered {
+        tiers: Vec<CommissionTier>,
+        #[serde(default)]
+        min: f64,
+    },
+}
+
+/// One step of a tiered schedule: `rate` applies once `monthly_volume` shares have traded
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct CommissionTier {
+    pub monthly_volume: i64,
+    pub rate: f64,
+}
+
+/// Sell-side regulatory fees, zero by default
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegulatoryFees {
+    /// SEC Section 31 fee, dollars per million dollars of sale proceeds (e.g. 27.80)
+    pub sec_fee_per_million: f64,
+    /// FINRA Trading Activity Fee per share sold (e.g. 0.000166)
+    pub finra_taf_per_share: f64,
+    /// Cap on the TAF per order, across its fills (e.g. 8.30)
+    pub finra_taf_max: f64,
+}
+
+/// Costs charged on a single fill
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct FeeBreakdown {
+    pub commission: f64,
+    pub sec_fee: f64,
+    pub finra_taf: f64,
+}
+
+/// Shares filled and fees charged so far on one order, updated by `Fees::charge`
+///
+/// Lets limits that apply per order (`PerShare` and `Tiered` minimums and maximums,
+/// `PerTrade`, the FINRA TAF cap) charge a partially filled order once rather than
+/// on every fill.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OrderCommission {
+    pub shares: i64,
+    /// Commission charged
+    pub paid: f64,
+    /// Commission before the schedule's minimum and maximum
+    pub unclamped: f64,
+    /// FINRA TAF charged
+    pub finra_taf: f64,
+}
+
+impl FeeBreakdown {
+    pub fn total(&self) -> f64 {
+        self.commission + self.sec_fee + self.finra_taf
+    }
+}
+
+impl CommissionModel {
+    /// Check rates and limits are non-negative and tiers are ordered
+    pub fn validate(&self) -> Result<(), String> {
+        let non_negative = |name: &str, v: f64| {
+            if v.is_finite() && v >= 0.0 {
+                Ok(())
+            } else {
+                Err(format!("commission {} must be >= 0, got {}", name, v))
+            }
+        };
+        match self {
+            CommissionModel::None => Ok(()),
+            CommissionModel::PerShare { rate, min, max } => {
+                non_negative("rate", *rate)?;
+                non_negative("min", *min)?;
+                match max {
+                    Some(max) if max < min => Err(format!("commission max ({}) is below min ({})", max, min)),
+                    _ => Ok(()),
+                }
+            }
+            CommissionModel::PerTrade(v) => non_negative("per trade", *v),
+            CommissionModel::PercentOfNotional(v) => non_negative("percent", *v),
+            CommissionModel::Tiered { tiers, min } => {
+                non_negative("min", *min)?;
+                if tiers.is_empty() {
+                    return Err("commission Tiered needs at least one tier".to_string());
+                }
+                for tier in tiers {
+                    non_negative("tier rate", tier.rate)?;
+                }
+                if tiers.windows(2).any(|w| w[0].monthly_volume >= w[1].monthly_volume) {
+                    return Err("commission tiers must have increasing monthly_volume".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl RegulatoryFees {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, v) in [
+            ("sec_fee_per_million", self.sec_fee_per_million),
+            ("finra_taf_per_share", self.finra_taf_per_share),
+            ("finra_taf_max", self.finra_taf_max),
+        ] {
+            if !(v.is_finite() && v >= 0.0) {
+                return Err(format!("regulatory_fees.{} must be >= 0, got {}", name, v));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Charges commissions and regulatory fees, tracking monthly volume for tiered schedules
+#[derive(Debug, Clone)]
+pub struct Fees {
+    pub commission: CommissionModel,
+    pub regulatory: RegulatoryFees,
+    /// (year, month) that `monthly_volume` counts
+    month: Option<(i32, u32)>,
+    monthly_volume: i64,
+}
+
+impl Fees {
+    pub fn new(commission: CommissionModel, regulatory: RegulatoryFees) -> Self {
+        Self {
+            commission,
+            regulatory,
+            month: None,
+            monthly_volume: 0,
+        }
+    }
+
+    /// Shares traded so far in the current calendar month
+    pub fn monthly_volume(&self) -> i64 {
+        self.monthly_volume
+    }
+
+    /// Costs of a fill of `size` shares at `price`, adding it to the monthly volume
+    ///
+    /// `order` holds what the order's earlier fills were charged and is updated with this
+    /// one: the order pays the difference between its clamped running total and what it
+    /// already paid.
+    pub fn charge(&mut self, price: f64, size: i64, is_sell: bool, timestamp: i64, order: &mut OrderCommission) -> FeeBreakdown {
+        let date = DateTime::from_timestamp_nanos(timestamp).date_naive();
+        let month = (date.year(), date.month());
+        if self.month != Some(month) {
+            self.month = Some(month);
+            self.monthly_volume = 0;
+        }
+
+        let shares = size.abs() as f64;
+        let notional = price * shares;
+        let commission = match &self.commission {
+            CommissionModel::None => 0.0,
+            CommissionModel::PerShare { rate, min, max } => {
+                order.unclamped += rate * shares;
+                let total = order.unclamped.max(*min);
+                let total = max.map_or(total, |max| total.min(max));
+                (total - order.paid).max(0.0)
+            }
+            CommissionModel::PerTrade(fee) if order.shares == 0 => *fee,
+            CommissionModel::PerTrade(_) => 0.0,
+            CommissionModel::PercentOfNotional(pct) => notional * pct / 100.0,
+            CommissionModel::Tiered { tiers, min } => {
+                let rate = tiers
+                    .iter()
+                    .rev()
+                    .find(|tier| self.monthly_volume >= tier.monthly_volume)
+                    .or(tiers.first())
+                    .map_or(0.0, |tier| tier.rate);
+                order.unclamped += rate * shares;
+                (order.unclamped.max(*min) - order.paid).max(0.0)
+            }
+        };
+        self.monthly_volume += size.abs();
+        order.shares += size.abs();
+        order.paid += commission;
+
+        let (sec_fee, finra_taf) = if is_sell {
+            (
+                notional * self.regulatory.sec_fee_per_million / 1_000_000.0,
+                (shares * self.regulatory.finra_taf_per_share)
+                    .min((self.regulatory.finra_taf_max - order.finra_taf).max(0.0)),
+            )
+        } else {
+            (0.0, 0.0)
+        };
+        order.finra_taf += finra_taf;
+
+        FeeBreakdown { commission, sec_fee, finra_taf }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 3600 * 1_000_000_000;
+
+    #[test]
+    fn test_per_share_min_max() {
+        let model = CommissionModel::PerShare { rate: 0.005, min: 1.0, max: Some(5.0) };
+        let mut fees = Fees::new(model, RegulatoryFees::default());
+        assert_eq!(fees.charge(10.0, 100, false, 0, &mut OrderCommission::default()).commission, 1.0);
+        assert_eq!(fees.charge(10.0, 500, false, 0, &mut OrderCommission::default()).commission, 2.5);
+        assert_eq!(fees.charge(10.0, 5000, false, 0, &mut OrderCommission::default()).commission, 5.0);
+    }
+
+    #[test]
+    fn test_limits_apply_per_order() {
+        let model = CommissionModel::PerShare { rate: 0.005, min: 1.0, max: Some(5.0) };
+        let mut fees = Fees::new(model, RegulatoryFees::default());
+        let mut order = OrderCommission::default();
+        // The minimum is charged once, then the rate until the order's total reaches the maximum
+        for (size, expected) in [(100, 1.0), (100, 0.0), (300, 1.5), (2000, 2.5), (100, 0.0)] {
+            let charged = fees.charge(10.0, size, false, 0, &mut order);
+            assert!((charged.commission - expected).abs() < 1e-9, "{} shares: {:?}", size, charged);
+        }
+        assert!((order.paid - 5.0).abs() < 1e-9);
+
+        let mut fees = Fees::new(CommissionModel::PerTrade(1.0), RegulatoryFees::default());
+        let mut order = OrderCommission::default();
+        assert_eq!(fees.charge(10.0, 100, false, 0, &mut order).commission, 1.0);
+        assert_eq!(fees.charge(10.0, 100, false, 0, &mut order).commission, 0.0);
+
+        let tiers = vec![CommissionTier { monthly_volume: 0, rate: 0.01 }];
+        let regulatory = RegulatoryFees { finra_taf_per_share: 0.01, finra_taf_max: 1.5, ..RegulatoryFees::default() };
+        let mut fees = Fees::new(CommissionModel::Tiered { tiers, min: 1.0 }, regulatory);
+        let mut order = OrderCommission::default();
+        let fills: Vec<(f64, f64)> = [50, 50, 100]
+            .into_iter()
+            .map(|size| fees.charge(10.0, size, true, 0, &mut order))
+            .map(|fees| (fees.commission, fees.finra_taf))
+            .collect();
+        assert_eq!(fills, vec![(1.0, 0.5), (0.0, 0.5), (1.0, 0.5)]);
+    }
+
+    #[test]
+    fn test_tiered_resets_each_month() {
+        let tiers = vec![
+            CommissionTier { monthly_volume: 0, rate: 0.01 },
+            CommissionTier { monthly_volume: 1000, rate: 0.005 },
+        ];
+        let mut fees = Fees::new(CommissionModel::Tiered { tiers, min: 0.0 }, RegulatoryFees::default());
+        assert_eq!(fees.charge(10.0, 1000, false, 0, &mut OrderCommission::default()).commission, 10.0);
+        assert_eq!(fees.charge(10.0, 1000, false, DAY, &mut OrderCommission::default()).commission, 5.0);
+        // 1970-02-01, new month
+        assert_eq!(fees.charge(10.0, 1000, false, 31 * DAY, &mut OrderCommission::default()).commission, 10.0);
+        assert_eq!(fees.monthly_volume(), 1000);
+    }
+
+    #[test]
+    fn test_regulatory_fees_on_sells_only() {
+        let regulatory = RegulatoryFees { sec_fee_per_million: 27.8, finra_taf_per_share: 0.000166, finra_taf_max: 8.3 };
+        let mut fees = Fees::new(CommissionModel::None, regulatory);
+        assert_eq!(fees.charge(100.0, 1000, false, 0, &mut OrderCommission::default()).total(), 0.0);
+
+        let sell = fees.charge(100.0, 1000, true, 0, &mut OrderCommission::default());
+        assert!((sell.sec_fee - 2.78).abs() < 1e-9);
+        assert!((sell.finra_taf - 0.166).abs() < 1e-9);
+        assert_eq!(fees.charge(100.0, 100_000, true, 0, &mut OrderCommission::default()).finra_taf, 8.3);
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use chrono::{DateTime, NaiveDate};
+use serde::Deserialize;
+use crate::indicators::indicator::Indicator;
+use crate::indicators::indicators::ADV;
+use crate::indicators::trackers::{VarianceTracker, WindowTracker};
+use crate::indicators::window::Window;
+use crate::types::ohlcv::Row;
+
+/// Price impact of an order from its size relative to average daily volume (ADV)
+///
+/// Impact as a fraction of the price is `coefficient * sigma * (size / ADV)^exponent`,
+/// with `sigma` the standard deviation of close-to-close returns over the last
+/// `volatility_bars` bars. Written in config files as e.g.
+/// `impact = { SquareRoot = { coefficient = 1.0, adv_days = 20, volatility_bars = 20 } }`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+pub enum ImpactModel {
+    /// No impact
+    #[default]
+    None,
+    /// Square-root law, exponent 0.5
+    SquareRoot { coefficient: f64, adv_days: usize, volatility_bars: usize },
+    /// Impact proportional to the order's share of ADV, exponent 1
+    Linear { coefficient: f64, adv_days: usize, volatility_bars: usize },
+}
+
+impl ImpactModel {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            ImpactModel::None => Ok(()),
+            ImpactModel::SquareRoot { coefficient, adv_days, volatility_bars }
+            | ImpactModel::Linear { coefficient, adv_days, volatility_bars } => {
+                if !(coefficient.is_finite() && coefficient >= 0.0) {
+                    return Err(format!("impact coefficient must be >= 0, got {}", coefficient));
+                }
+                if adv_days == 0 || volatility_bars < 2 {
+                    return Err(format!(
+                        "impact needs adv_days >= 1 and volatility_bars >= 2, got {} and {}",
+                        adv_days, volatility_bars
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// ADV and return volatility of one ticker
+#[derive(Debug)]
+struct TickerVolume {
+    adv: ADV,
+    date: NaiveDate,
+    last_close: f64,
+    returns: VarianceTracker,
+}
+
+/// Applies an `ImpactModel` to fills, tracking ADV and volatility per ticker
+#[derive(Debug)]
+pub struct MarketImpact {
+    pub model: ImpactModel,
+    tickers: HashMap<String, TickerVolume>,
+}
+
+impl MarketImpact {
+    pub fn new(model: ImpactModel) -> Self {
+        Self {
+            model,
+            tickers: HashMap::new(),
+        }
+    }
+
+    /// Feed a new bar
+    pub fn update(&mut self, row: &Row) {
+        let (ImpactModel::SquareRoot { adv_days, volatility_bars, .. }
+        | ImpactModel::Linear { adv_days, volatility_bars, .. }) = self.model
+        else {
+            return;
+        };
+        let date = DateTime::from_timestamp_nanos(row.timestamp).date_naive();
+        match self.tickers.get_mut(&row.ticker) {
+            Some(state) => {
+                // The first bar of a day closes out the previous one
+                if date != state.date {
+                    state.adv.on_market_close();
+                    state.date = date;
+                }
+                state.adv.update(row);
+                if state.last_close > 0.0 {
+                    state.returns.push(row.timestamp, row.close / state.last_close - 1.0);
+                }
+                state.last_close = row.close;
+            }
+            None => {
+                let mut adv = ADV::new(adv_days);
+                adv.update(row);
+                self.tickers.insert(row.ticker.clone(), TickerVolume {
+                    adv,
+                    date,
+                    last_close: row.close,
+                    returns: VarianceTracker::new(Window::Bars(volatility_bars)),
+                });
+            }
+        }
+    }
+
+    /// Impact per share of filling `size` shares of `ticker` at `price`, always >= 0
+    ///
+    /// Zero until a full day of volume has been seen for the ticker.
+    pub fn per_share(&self, ticker: &str, price: f64, size: i64) -> f64 {
+        let (coefficient, exponent) = match self.model {
+            ImpactModel::None => return 0.0,
+            ImpactModel::SquareRoot { coefficient, .. } => (coefficient, 0.5),
+            ImpactModel::Linear { coefficient, .. } => (coefficient, 1.0),
+        };
+        let Some(state) = self.tickers.get(ticker) else {
+            return 0.0;
+        };
+        match (state.adv.get(), state.returns.get()) {
+            (Some(adv), Some(sigma)) if adv > 0.0 => {
+                price * coefficient * sigma * (size.abs() as f64 / adv).powf(exponent)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Move `price` against the trade: buys pay more, sells receive less
+    ///
+    /// Returns the adjusted price and the impact per share.
+    pub fn apply(&self, ticker: &str, price: f64, size: i64, is_buy: bool) -> (f64, f64) {
+        let impact = self.per_share(ticker, price, size);
+        if is_buy {
+            (price + impact, impact)
+        } else {
+            (price - impact, impact)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 3600 * 1_000_000_000;
+
+    fn bar(timestamp: i64, close: f64) -> Row {
+        Row {
+            timestamp,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 10_000,
+            ticker: "AAPL".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_square_root_impact_scales_with_participation() {
+        let model = ImpactModel::SquareRoot { coefficient: 1.0, adv_days: 5, volatility_bars: 10 };
+        let mut impact = MarketImpact::new(model);
+        for (i, close) in [100.0, 101.0, 100.0, 101.0].into_iter().enumerate() {
+            impact.update(&bar(i as i64, close));
+        }
+        // No complete day yet
+        assert_eq!(impact.per_share("AAPL", 100.0, 1_000), 0.0);
+
+        // ADV is 40k once the next day starts
+        impact.update(&bar(DAY, 100.0));
+        let small = impact.per_share("AAPL", 100.0, 400);
+        let large = impact.per_share("AAPL", 100.0, 40_000);
+        assert!(small > 0.0);
+        assert!((large / small - 10.0).abs() < 1e-9);
+        assert_eq!(impact.apply("AAPL", 100.0, 400, false), (100.0 - small, small));
+        assert_eq!(impact.per_share("MSFT", 100.0, 400), 0.0);
+    }
+}
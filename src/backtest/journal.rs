@@ -0,0 +1,147 @@
+use std::path::Path;
+use serde::Serialize;
+use crate::position::order::{Order, OrderId, OrderType};
+
+/// Stage of an order's life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum OrderEventKind {
+    /// A strategy (or the portfolio, for replacement, bracket and liquidation orders) sent it
+    Submitted,
+    /// It passed the submission checks and is working
+    Accepted,
+    /// It, or one of its fills, was refused; see the event's reason
+    Rejected,
+    /// Part of it filled and the rest is still working or was cancelled
+    PartiallyFilled,
+    /// All of it has filled
+    Filled,
+    /// Its time in force ran out
+    Expired,
+    /// It stopped working for any other reason; see the event's reason
+    Cancelled,
+    /// A replace request changed its prices, size or time in force
+    Replaced,
+    /// A cancel request for it came too late or was invalid; the order itself is unaffected
+    CancelRejected,
+    /// A replace request for it came too late or was invalid; the order itself is unaffected
+    ReplaceRejected,
+}
+
+impl OrderEventKind {
+    /// Whether the event is about a request for the order rather than the order itself
+    pub fn is_refused_request(&self) -> bool {
+        matches!(self, OrderEventKind::CancelRejected | OrderEventKind::ReplaceRejected)
+    }
+}
+
+/// One step of an order's life
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OrderEvent {
+    pub timestamp: i64,
+    pub order_id: OrderId,
+    pub kind: OrderEventKind,
+    pub ticker: String,
+    pub strategy_name: String,
+    /// Order type and distances, as written in code
+    pub order_type: String,
+    /// Size of the order at the time of the event
+    pub size: i64,
+    /// Shares filled so far
+    pub filled: i64,
+    /// Fill price, for fill events
+    pub price: Option<f64>,
+    /// Why it was rejected or cancelled, empty otherwise
+    pub reason: String,
+}
+
+impl OrderEvent {
+    pub fn new(timestamp: i64, order_id: OrderId, kind: OrderEventKind, ticker: &str, strategy_name: &str) -> Self {
+        Self {
+            timestamp,
+            order_id,
+            kind,
+            ticker: ticker.to_string(),
+            strategy_name: strategy_name.to_string(),
+            order_type: String::new(),
+            size: 0,
+            filled: 0,
+            price: None,
+            reason: String::new(),
+        }
+    }
+
+    pub fn with_order_type(mut self, order_type: OrderType, size: i64) -> Self {
+        self.order_type = format!("{:?}", order_type);
+        self.size = size;
+        self
+    }
+
+    /// Type, size and filled shares of `order`
+    pub fn with_order(self, order: &Order) -> Self {
+        let filled = order.fill_size;
+        Self { filled, ..self.with_order_type(order.order_type, order.size) }
+    }
+
+    pub fn with_price(mut self, price: f64) -> Self {
+        self.price = Some(price);
+        self
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = reason.into();
+        self
+    }
+}
+
+/// Every order event of a run, in the order they happened
+#[derive(Debug, Clone, Default)]
+pub struct OrderJournal {
+    events: Vec<OrderEvent>,
+}
+
+impl OrderJournal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: OrderEvent) {
+        self.events.push(event);
+    }
+
+    pub fn events(&self) -> &[OrderEvent] {
+        &self.events
+    }
+
+    /// Life of one order
+    pub fn order(&self, id: OrderId) -> impl Iterator<Item = &OrderEvent> {
+        self.events.iter().filter(move |event| event.order_id == id)
+    }
+
+    /// Events of one kind, e.g. all rejections
+    pub fn of_kind(&self, kind: OrderEventKind) -> impl Iterator<Item = &OrderEvent> {
+        self.events.iter().filter(move |event| event.kind == kind)
+    }
+
+    /// Latest event that changed an order, i.e. its current state; refused requests are skipped
+    pub fn last(&self, id: OrderId) -> Option<&OrderEvent> {
+        self.events.iter().rev().find(|event| event.order_id == id && !event.kind.is_refused_request())
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// Write order events to a CSV file, one row per event
+pub fn write_csv(events: &[OrderEvent], path: &Path) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for event in events {
+        writer.serialize(event)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
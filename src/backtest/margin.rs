@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+
+/// Margin requirements as fractions of a position's market value
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarginRequirement {
+    /// Equity needed to open a position (Reg-T: 0.5)
+    pub initial: f64,
+    /// Equity needed to keep it open (FINRA minimum: 0.25)
+    pub maintenance: f64,
+}
+
+impl MarginRequirement {
+    fn validate(&self, name: &str) -> Result<(), String> {
+        let in_range = |v: f64| v.is_finite() && v > 0.0 && v <= 1.0;
+        if !in_range(self.initial) || !in_range(self.maintenance) {
+            return Err(format!(
+                "{} margin must be in (0, 1], got initial {} and maintenance {}",
+                name, self.initial, self.maintenance
+            ));
+        }
+        if self.maintenance > self.initial {
+            return Err(format!(
+                "{} maintenance margin ({}) is above initial margin ({})",
+                name, self.maintenance, self.initial
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Account margin settings
+///
+/// The default is a cash account: positions need their full value in equity
+/// (`initial = 1.0`). Set `initial = 0.5` for a Reg-T margin account.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarginConfig {
+    /// Initial margin for tickers without an override
+    pub initial: f64,
+    /// Maintenance margin for tickers without an override
+    pub maintenance: f64,
+    /// Per-ticker requirements, e.g. for volatile or concentrated names
+    pub overrides: HashMap<String, MarginRequirement>,
+    /// Maximum gross exposure / equity; None leaves only the margin limits
+    pub max_leverage: Option<f64>,
+    /// Close positions when equity falls below the maintenance requirement
+    pub liquidate: bool,
+}
+
+impl Default for MarginConfig {
+    fn default() -> Self {
+        Self {
+            initial: 1.0,
+            maintenance: 0.25,
+            overrides: HashMap::new(),
+            max_leverage: None,
+            liquidate: true,
+        }
+    }
+}
+
+impl MarginConfig {
+    /// Requirement for a ticker, using the override if there is one
+    pub fn requirement(&self, ticker: &str) -> MarginRequirement {
+        self.overrides.get(ticker).copied().unwrap_or(MarginRequirement {
+            initial: self.initial,
+            maintenance: self.maintenance,
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.requirement("").validate("default")?;
+        for (ticker, requirement) in &self.overrides {
+            requirement.validate(ticker)?;
+        }
+        match self.max_leverage {
+            Some(max) if !(max.is_finite() && max > 0.0) => Err(format!("margin.max_leverage must be > 0, got {}", max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Balances of the account at the last seen prices
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AccountState {
+    pub cash: f64,
+    /// Market value of long positions
+    pub long_market_value: f64,
+    /// Market value of short positions (positive)
+    pub short_market_value: f64,
+    /// Cash + long market value - short market value
+    pub equity: f64,
+    /// Equity needed to hold the open positions at initial margin
+    pub initial_margin: f64,
+    /// Equity below which a margin call is issued
+    pub maintenance_margin: f64,
+}
+
+impl AccountState {
+    pub fn gross_exposure(&self) -> f64 {
+        self.long_market_value + self.short_market_value
+    }
+
+    /// Gross exposure / equity
+    pub fn leverage(&self) -> f64 {
+        if self.equity > 0.0 {
+            self.gross_exposure() / self.equity
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    /// Equity not tied up by initial margin
+    pub fn available_funds(&self) -> f64 {
+        self.equity - self.initial_margin
+    }
+
+    /// Equity above the maintenance requirement; negative means a margin call
+    pub fn excess_liquidity(&self) -> f64 {
+        self.equity - self.maintenance_margin
+    }
+}
@@ -0,0 +1,308 @@
+use std::fmt;
+use chrono::{DateTime, Duration, NaiveDate};
+use serde::Serialize;
+use crate::position::strategy::Action;
+use crate::types::log::TradeLog;
+
+/// Trading days per year, used to annualize daily returns
+pub const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+const NANOS_PER_YEAR: f64 = 365.25 * 24.0 * 3600.0 * 1e9;
+
+/// Summary statistics of a finished backtest
+///
+/// Return and risk figures come from the equity curve, trade figures from the
+/// exit `TradeLog`s. Ratios that are undefined for the data (e.g. Sharpe with
+/// zero volatility, profit factor without losing trades) are `None`.
+///
+/// # Usage
+/// ```ignore
+/// let curve = realized_equity_curve(config.starting_buying_power, &engine.trade_logs);
+/// let report = PerformanceReport::compute(&engine.trade_logs, &curve, 0.0);
+/// println!("{}", report);
+/// ```
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PerformanceReport {
+    /// Final equity / starting equity - 1
+    pub total_return: f64,
+    /// Compound annual growth rate over the calendar span of the curve
+    pub cagr: Option<f64>,
+    /// Annualized standard deviation of daily returns
+    pub volatility: Option<f64>,
+    pub sharpe: Option<f64>,
+    pub sortino: Option<f64>,
+    /// CAGR / max drawdown
+    pub calmar: Option<f64>,
+    /// Largest peak-to-trough decline, as a positive fraction of the peak
+    pub max_drawdown: f64,
+    /// Longest time spent below a previous equity peak
+    #[serde(serialize_with = "serialize_duration")]
+    pub max_drawdown_duration: Duration,
+    /// Number of closed trades
+    pub trades: usize,
+    pub win_rate: Option<f64>,
+    /// Gross profit / gross loss
+    pub profit_factor: Option<f64>,
+    /// Average PnL per closed trade
+    pub expectancy: Option<f64>,
+    pub avg_win: Option<f64>,
+    /// Average PnL of losing trades (negative)
+    pub avg_loss: Option<f64>,
+    #[serde(serialize_with = "serialize_optional_duration")]
+    pub avg_holding_time: Option<Duration>,
+}
+
+fn serialize_duration<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_i64(d.num_seconds())
+}
+
+fn serialize_optional_duration<S: serde::Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    match d {
+        Some(d) => s.serialize_some(&d.num_seconds()),
+        None => s.serialize_none(),
+    }
+}
+
+impl PerformanceReport {
+    /// Compute the report from trade logs and an equity curve
+    ///
+    /// * `equity_curve` - `(timestamp_ns, equity)` pairs in time order, e.g. one per bar
+    /// * `risk_free_rate` - annual rate subtracted from returns for Sharpe and Sortino
+    ///
+    /// Volatility, Sharpe and Sortino use the last equity value of each calendar day,
+    /// annualized with `TRADING_DAYS_PER_YEAR`.
+    pub fn compute(trade_logs: &[TradeLog], equity_curve: &[(i64, f64)], risk_free_rate: f64) -> Self {
+        let mut report = Self::default();
+        report.add_equity_stats(equity_curve, risk_free_rate);
+        report.add_trade_stats(trade_logs);
+        report
+    }
+
+    fn add_equity_stats(&mut self, curve: &[(i64, f64)], risk_free_rate: f64) {
+        let (Some(&(start_ts, start)), Some(&(end_ts, end))) = (curve.first(), curve.last()) else {
+            return;
+        };
+        if start <= 0.0 {
+            return;
+        }
+        self.total_return = end / start - 1.0;
+
+        let years = (end_ts - start_ts) as f64 / NANOS_PER_YEAR;
+        if years > 0.0 && end > 0.0 {
+            self.cagr = Some((end / start).powf(1.0 / years) - 1.0);
+        }
+
+        let (max_drawdown, duration) = drawdown(curve);
+        self.max_drawdown = max_drawdown;
+        self.max_drawdown_duration = duration;
+        if max_drawdown > 0.0 {
+            self.calmar = self.cagr.map(|cagr| cagr / max_drawdown);
+        }
+
+        let returns = daily_returns(curve);
+        if returns.len() < 2 {
+            return;
+        }
+        let rf_daily = risk_free_rate / TRADING_DAYS_PER_YEAR;
+        let excess: Vec<f64> = returns.iter().map(|r| r - rf_daily).collect();
+        let mean_excess = mean(&excess);
+        let annualize = TRADING_DAYS_PER_YEAR.sqrt();
+
+        let std = std_dev(&returns);
+        self.volatility = Some(std * annualize);
+        if std > 0.0 {
+            self.sharpe = Some(mean_excess / std * annualize);
+        }
+
+        let downside = (excess.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / excess.len() as f64).sqrt();
+        if downside > 0.0 {
+            self.sortino = Some(mean_excess / downside * annualize);
+        }
+    }
+
+    fn add_trade_stats(&mut self, trade_logs: &[TradeLog]) {
+        let exits: Vec<&TradeLog> = trade_logs.iter().filter(|log| log.action == Action::Exit).collect();
+        self.trades = exits.len();
+        if exits.is_empty() {
+            return;
+        }
+
+        let wins: Vec<f64> = exits.iter().map(|log| log.pnl).filter(|pnl| *pnl > 0.0).collect();
+        let losses: Vec<f64> = exits.iter().map(|log| log.pnl).filter(|pnl| *pnl < 0.0).collect();
+        let gross_profit: f64 = wins.iter().sum();
+        let gross_loss: f64 = -losses.iter().sum::<f64>();
+
+        self.win_rate = Some(wins.len() as f64 / exits.len() as f64);
+        self.expectancy = Some(exits.iter().map(|log| log.pnl).sum::<f64>() / exits.len() as f64);
+        if gross_loss > 0.0 {
+            self.profit_factor = Some(gross_profit / gross_loss);
+        }
+        if !wins.is_empty() {
+            self.avg_win = Some(mean(&wins));
+        }
+        if !losses.is_empty() {
+            self.avg_loss = Some(mean(&losses));
+        }
+
+        let holding: Vec<i64> = exits
+            .iter()
+            .filter_map(|log| log.position.exit_timestamp.map(|exit| exit - log.position.entry_timestamp))
+            .collect();
+        if !holding.is_empty() {
+            let avg = holding.iter().map(|&ns| ns as i128).sum::<i128>() / holding.len() as i128;
+            self.avg_holding_time = Some(Duration::nanoseconds(avg as i64));
+        }
+    }
+}
+
+/// Equity curve from realized PnL only: starting capital plus cumulative PnL at each exit
+///
+/// Useful when no mark-to-market curve was recorded; open positions are ignored.
+pub fn realized_equity_curve(starting_equity: f64, trade_logs: &[TradeLog]) -> Vec<(i64, f64)> {
+    let mut exits: Vec<(i64, f64)> = trade_logs
+        .iter()
+        .filter(|log| log.action == Action::Exit)
+        .filter_map(|log| log.position.exit_timestamp.map(|ts| (ts, log.pnl)))
+        .collect();
+    exits.sort_by_key(|(ts, _)| *ts);
+
+    let start_ts = trade_logs.iter().map(|log| log.position.entry_timestamp).min();
+    let mut equity = starting_equity;
+    let mut curve: Vec<(i64, f64)> = start_ts.map(|ts| (ts, equity)).into_iter().collect();
+    for (ts, pnl) in exits {
+        equity += pnl;
+        curve.push((ts, equity));
+    }
+    curve
+}
+
+/// Max drawdown fraction and the longest time spent below a previous peak
+fn drawdown(curve: &[(i64, f64)]) -> (f64, Duration) {
+    let mut peak = f64::MIN;
+    let mut peak_ts = 0;
+    let mut max_drawdown: f64 = 0.0;
+    let mut longest = 0;
+    for &(ts, equity) in curve {
+        if equity >= peak {
+            peak = equity;
+            peak_ts = ts;
+        } else {
+            if peak > 0.0 {
+                max_drawdown = max_drawdown.max((peak - equity) / peak);
+            }
+            longest = longest.max(ts - peak_ts);
+        }
+    }
+    (max_drawdown, Duration::nanoseconds(longest))
+}
+
+/// Returns between the last equity value of consecutive calendar days
+fn daily_returns(curve: &[(i64, f64)]) -> Vec<f64> {
+    let mut closes: Vec<(NaiveDate, f64)> = Vec::new();
+    for &(ts, equity) in curve {
+        let date = DateTime::from_timestamp_nanos(ts).date_naive();
+        match closes.last_mut() {
+            Some((last, value)) if *last == date => *value = equity,
+            _ => closes.push((date, equity)),
+        }
+    }
+    closes
+        .windows(2)
+        .filter(|w| w[0].1 != 0.0)
+        .map(|w| w[1].1 / w[0].1 - 1.0)
+        .collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample standard deviation
+fn std_dev(values: &[f64]) -> f64 {
+    let m = mean(values);
+    (values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+}
+
+impl fmt::Display for PerformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn pct(v: Option<f64>) -> String {
+            v.map_or("n/a".to_string(), |v| format!("{:.2}%", v * 100.0))
+        }
+        fn num(v: Option<f64>) -> String {
+            v.map_or("n/a".to_string(), |v| format!("{:.2}", v))
+        }
+        fn dur(d: Duration) -> String {
+            format!("{}d {}h {}m", d.num_days(), d.num_hours() % 24, d.num_minutes() % 60)
+        }
+
+        writeln!(f, "total return: {}", pct(Some(self.total_return)))?;
+        writeln!(f, "cagr: {}", pct(self.cagr))?;
+        writeln!(f, "volatility: {}", pct(self.volatility))?;
+        writeln!(f, "sharpe: {}", num(self.sharpe))?;
+        writeln!(f, "sortino: {}", num(self.sortino))?;
+        writeln!(f, "calmar: {}", num(self.calmar))?;
+        writeln!(f, "max drawdown: {} over {}", pct(Some(self.max_drawdown)), dur(self.max_drawdown_duration))?;
+        writeln!(f, "closed trades: {}", self.trades)?;
+        writeln!(f, "win rate: {}", pct(self.win_rate))?;
+        writeln!(f, "profit factor: {}", num(self.profit_factor))?;
+        writeln!(f, "expectancy: {}", num(self.expectancy))?;
+        writeln!(f, "avg win / loss: {} / {}", num(self.avg_win), num(self.avg_loss))?;
+        write!(f, "avg holding time: {}", self.avg_holding_time.map_or("n/a".to_string(), dur))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::position::position::Position;
+    use crate::position::side::Side;
+
+    const DAY: i64 = 24 * 3600 * 1_000_000_000;
+
+    fn exit(pnl_per_share: f64, entry_ts: i64, exit_ts: i64) -> TradeLog {
+        let mut pos = Position::new("id".into(), "AAPL".into(), Side::Long, 10, 100.0, entry_ts);
+        pos.close(100.0 + pnl_per_share, exit_ts).unwrap();
+        TradeLog::new(pos, Action::Exit, "test".into(), "test".into(), HashMap::new())
+    }
+
+    #[test]
+    fn test_trade_stats() {
+        let logs = vec![exit(2.0, 0, DAY), exit(-1.0, 0, 3 * DAY), exit(4.0, DAY, 2 * DAY)];
+        let report = PerformanceReport::compute(&logs, &[], 0.0);
+
+        assert_eq!(report.trades, 3);
+        assert_eq!(report.win_rate, Some(2.0 / 3.0));
+        assert_eq!(report.profit_factor, Some(60.0 / 10.0));
+        assert_eq!(report.expectancy, Some(50.0 / 3.0));
+        assert_eq!(report.avg_win, Some(30.0));
+        assert_eq!(report.avg_loss, Some(-10.0));
+        assert_eq!(report.avg_holding_time, Some(Duration::nanoseconds(5 * DAY / 3)));
+    }
+
+    #[test]
+    fn test_equity_stats() {
+        // 100 -> 110 -> 99 -> 104.5 -> 121, one point per day
+        let curve: Vec<(i64, f64)> = [100.0, 110.0, 99.0, 104.5, 121.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &equity)| (i as i64 * DAY, equity))
+            .collect();
+        let report = PerformanceReport::compute(&[], &curve, 0.0);
+
+        assert!((report.total_return - 0.21).abs() < 1e-12);
+        assert!((report.max_drawdown - 0.1).abs() < 1e-12);
+        assert_eq!(report.max_drawdown_duration, Duration::days(2));
+        assert!(report.cagr.unwrap() > report.total_return);
+        assert!(report.sharpe.unwrap() > 0.0);
+        assert!(report.sortino.unwrap() > report.sharpe.unwrap());
+        assert_eq!(report.trades, 0);
+        assert!(report.win_rate.is_none());
+    }
+
+    #[test]
+    fn test_realized_equity_curve() {
+        let logs = vec![exit(-1.0, 0, 3 * DAY), exit(2.0, 0, DAY)];
+        let curve = realized_equity_curve(1000.0, &logs);
+        assert_eq!(curve, vec![(0, 1000.0), (DAY, 1020.0), (3 * DAY, 1010.0)]);
+    }
+}
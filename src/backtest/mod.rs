@@ -2,4 +2,13 @@ pub mod context;
 pub mod portfolio;
 pub mod engine;
 pub mod signal;
+pub mod metrics;
+pub mod equity;
+pub mod slippage;
+pub mod impact;
+pub mod fees;
+pub mod borrow;
+pub mod margin;
+pub mod execution;
+pub mod journal;
 
@@ -1,146 +1,694 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::collections::VecDeque;
-use crate::config::{self, ReplacementStrategy};
+use crate::config::{Config, ReplacementStrategy};
 use crate::position::side::Side;
 use crate::position::position::Position;
 use crate::types::log::TradeLog;
 use crate::backtest::signal::{Signal, SignalType};
-use crate::position::order::{Order, OrderType, OrderAction};
+use crate::position::order::{CancelReason, Execution, Order, OrderAction, OrderGroup, OrderId, OrderReplace, OrderTimeline, OrderType};
 use crate::position::strategy::Action;
 use crate::types::ohlcv::Row;
+use super::equity::{EquityCurve, EquitySnapshot};
+use super::slippage::Slippage;
+use super::impact::MarketImpact;
+use super::fees::{Fees, OrderCommission};
+use super::margin::AccountState;
+use super::journal::{OrderEvent, OrderEventKind, OrderJournal};
 use uuid::Uuid;
+use chrono::{DateTime, NaiveDate};
 
 pub struct PendingOrder {
+    pub id: OrderId,
     pub order: Order,
     pub ticker: String,
     pub strategy_name: String,
     pub indicator_values: HashMap<String, f64>,
+    /// Bars of this ticker still to pass before the order can fill, see `ExecutionTiming`
+    pub delay: usize,
+    /// Group the order belongs to, see `OrderGroup`
+    pub links: OrderLinks,
+    /// Cancel or replace request from the strategy, applied once the current bar is checked
+    pub request: Option<OrderRequest>,
+    /// Shares filled and commission charged on the order so far
+    pub commission: OrderCommission,
+}
+
+/// A strategy's request to change a working order
+///
+/// Requests come from signals on a bar's close, after the bar traded, so the order is
+/// still checked against that bar first: if it fills there, the fill stands and a
+/// cancel or replace only applies to what is left.
+#[derive(Debug, Clone)]
+pub enum OrderRequest {
+    Cancel,
+    /// The replacement, with the price and ATR to resolve its distances around
+    Replace { replace: OrderReplace, price: f64, atr: Option<f64>, timestamp: i64 },
+}
+
+impl OrderRequest {
+    pub fn kind(&self) -> RequestKind {
+        match self {
+            OrderRequest::Cancel => RequestKind::Cancel,
+            OrderRequest::Replace { .. } => RequestKind::Replace,
+        }
+    }
+}
+
+/// How a pending order is tied to others of its `OrderGroup`
+#[derive(Debug, Clone, Default)]
+pub struct OrderLinks {
+    /// OCO group shared with sibling orders; the first of them to fill cancels the rest
+    pub oco_group: Option<u64>,
+    /// Orders to submit once this one has filled (one-triggers-other)
+    pub triggers: Vec<OrderType>,
+    /// Whether the triggered orders form an OCO group, as a bracket's exits do
+    pub triggers_oco: bool,
+}
+
+/// An order refused before it was queued, and why
+#[derive(Debug, Clone)]
+pub struct OrderRejection {
+    pub timestamp: i64,
+    pub order_id: OrderId,
+    pub ticker: String,
+    pub strategy_name: String,
+    pub reason: String,
+}
+
+/// A cancel or replace request the portfolio could not carry out, and why
+#[derive(Debug, Clone)]
+pub struct RefusedRequest {
+    pub timestamp: i64,
+    /// The order the request was for
+    pub order_id: OrderId,
+    pub ticker: String,
+    pub strategy_name: String,
+    pub request: RequestKind,
+    pub reason: String,
+}
+
+/// Kind of a strategy's request for a working order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    Cancel,
+    Replace,
+}
+
+/// An order that expired or was cancelled, and how much of it had filled
+#[derive(Debug, Clone)]
+pub struct OrderCancellation {
+    pub timestamp: i64,
+    pub ticker: String,
+    pub strategy_name: String,
+    pub reason: CancelReason,
+    pub filled: i64,
+    pub unfilled: i64,
 }
 
 pub struct Portfolio {
-    pub buying_power: f64,
+    /// Cash balance, including short sale proceeds
+    pub cash: f64,
     pub open_positions: HashMap<String, Position>, // Ticker -> Position
     pub closed_positions: Vec<Position>,
     pub pending_orders: VecDeque<PendingOrder>, // FIFO queue for pending orders
+    pub config: Config,
+    /// Last close seen for each ticker, used to value open positions
+    pub last_prices: HashMap<String, f64>,
+    /// Mark-to-market equity, recorded once per bar
+    pub equity_curve: EquityCurve,
+    /// Slippage applied to market and stop-market fills
+    pub slippage: Slippage,
+    /// Market impact applied to market and stop-market fills
+    pub impact: MarketImpact,
+    /// Commissions and regulatory fees charged on every fill
+    pub fees: Fees,
+    /// Orders refused before reaching the queue (e.g. shorts without a locate)
+    pub rejections: Vec<OrderRejection>,
+    /// Cancel and replace requests that came too late or were invalid
+    pub refused_requests: Vec<RefusedRequest>,
+    /// Orders that expired or were cancelled after being queued
+    pub cancellations: Vec<OrderCancellation>,
+    /// Fills whose outcome depended on `execution.intrabar_path`: legs of one OCO group
+    /// reached on the same bar, or stop-limits triggered mid-bar
+    pub ambiguous_fills: usize,
+    /// Lifecycle events of every order
+    pub journal: OrderJournal,
+    /// Last id handed to an OCO group
+    next_group_id: u64,
+    /// Last id handed to an order
+    next_order_id: u64,
+    /// Last day borrow fees were charged for
+    borrow_date: Option<NaiveDate>,
 }
 
 impl Portfolio {
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
         Self {
-            buying_power: config::get_config().starting_buying_power,
+            cash: config.starting_buying_power,
             open_positions: HashMap::new(),
             closed_positions: Vec::new(),
             pending_orders: VecDeque::new(),
+            slippage: Slippage::new(config.slippage),
+            impact: MarketImpact::new(config.impact),
+            fees: Fees::new(config.commission.clone(), config.regulatory_fees),
+            rejections: Vec::new(),
+            refused_requests: Vec::new(),
+            cancellations: Vec::new(),
+            ambiguous_fills: 0,
+            journal: OrderJournal::new(),
+            next_group_id: 0,
+            next_order_id: 0,
+            borrow_date: None,
+            config,
+            last_prices: HashMap::new(),
+            equity_curve: EquityCurve::new(),
+        }
+    }
+
+    pub fn update_prices(&mut self, ticker: &str, price: f64) {
+        self.last_prices.insert(ticker.to_string(), price);
+    }
+
+    /// Charge borrow fees on open shorts for each calendar day since the last call
+    ///
+    /// Called with every bar; fees are only charged when the date changes, so
+    /// shorts opened and closed on the same day pay nothing.
+    pub fn accrue_borrow(&mut self, timestamp: i64) {
+        let date = DateTime::from_timestamp_nanos(timestamp).date_naive();
+        let days = match self.borrow_date {
+            Some(last) if date > last => (date - last).num_days(),
+            Some(_) => return,
+            None => 0,
+        };
+        self.borrow_date = Some(date);
+        if days == 0 {
+            return;
+        }
+
+        let costs: Vec<(String, f64)> = self.open_positions
+            .values()
+            .filter(|pos| matches!(pos.side, Side::Short))
+            .map(|pos| {
+                let notional = self.mark_price(pos) * pos.size as f64;
+                (pos.ticker.clone(), self.config.borrow.borrow_cost(&pos.ticker, notional, days))
+            })
+            .collect();
+        for (ticker, cost) in costs {
+            if let Some(pos) = self.open_positions.get_mut(&ticker) {
+                pos.borrow_fees += cost;
+                self.cash -= cost;
+            }
         }
     }
 
-    pub fn update_prices(&mut self, _ticker: &str, _price: f64) {
-        // In this simple model, we don't store current price in Position struct persistently.
-        // We could track unrealized PnL here if needed.
+    /// Last seen price of a ticker, falling back to the entry price of its position
+    fn mark_price(&self, pos: &Position) -> f64 {
+        self.last_prices.get(&pos.ticker).copied().unwrap_or(pos.entry_price)
+    }
+
+    /// Unrealized PnL of all open positions at the last seen prices
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.open_positions
+            .values()
+            .map(|pos| pos.unrealized_pnl(self.mark_price(pos)))
+            .sum()
     }
 
-    /// Process a new signal, potentially creating a pending order
+    /// Balances and margin requirements at the last seen prices
+    pub fn account(&self) -> AccountState {
+        let mut account = AccountState { cash: self.cash, ..AccountState::default() };
+        for pos in self.open_positions.values() {
+            let value = self.mark_price(pos) * pos.size as f64;
+            match pos.side {
+                Side::Long => account.long_market_value += value,
+                Side::Short => account.short_market_value += value,
+                Side::None => continue,
+            }
+            let requirement = self.config.margin.requirement(&pos.ticker);
+            account.initial_margin += value * requirement.initial;
+            account.maintenance_margin += value * requirement.maintenance;
+        }
+        // Short sale proceeds are in cash, so shorts are a liability
+        account.equity = self.cash + account.long_market_value - account.short_market_value;
+        account
+    }
+
+    /// Value of new positions the account can open at the default initial margin
+    pub fn buying_power(&self) -> f64 {
+        (self.account().available_funds() / self.config.margin.initial).max(0.0)
+    }
+
+    /// Check a new position worth `notional` fits the margin and leverage limits
+    pub fn can_open(&self, ticker: &str, notional: f64) -> Result<(), String> {
+        let account = self.account();
+        let required = notional.abs() * self.config.margin.requirement(ticker).initial;
+        if required > account.available_funds() {
+            return Err(format!(
+                "insufficient buying power for {}: needs {:.2} initial margin, {:.2} available",
+                ticker, required, account.available_funds()
+            ));
+        }
+        if let Some(max) = self.config.margin.max_leverage {
+            let leverage = (account.gross_exposure() + notional.abs()) / account.equity;
+            if leverage > max {
+                return Err(format!("{} would raise leverage to {:.2}, above the {:.2} limit", ticker, leverage, max));
+            }
+        }
+        Ok(())
+    }
+
+    /// Liquidate positions, largest first, while equity is below the maintenance requirement
+    ///
+    /// Positions are closed at their last seen price. Returns the exit logs,
+    /// marked with the condition name `MarginCall`; the orders are journalled with
+    /// the reason `margin call`.
+    pub fn check_margin(&mut self, timestamp: i64) -> Vec<TradeLog> {
+        let mut logs = Vec::new();
+        if !self.config.margin.liquidate {
+            return logs;
+        }
+        while self.account().excess_liquidity() < 0.0 {
+            let Some((ticker, price)) = self.open_positions
+                .values()
+                .map(|pos| (pos.ticker.clone(), self.mark_price(pos), pos.size as f64))
+                .max_by(|a, b| (a.1 * a.2).total_cmp(&(b.1 * b.2)))
+                .map(|(ticker, price, _)| (ticker, price))
+            else {
+                break;
+            };
+            let pos = &self.open_positions[&ticker];
+            let close_type = match pos.side {
+                Side::Long => OrderType::MarketSell(),
+                _ => OrderType::MarketBuy(),
+            };
+            let execution = Execution { timestamp, price, size: pos.size };
+            let Ok(order) = Order::new(close_type, OrderAction::Close, timestamp, None, pos.size) else {
+                break;
+            };
+            let mut pending = PendingOrder {
+                id: self.new_order_id(),
+                order,
+                ticker: ticker.clone(),
+                strategy_name: "Liquidation".to_string(),
+                indicator_values: HashMap::new(),
+                delay: 0,
+                links: OrderLinks::default(),
+                request: None,
+                commission: OrderCommission::default(),
+            };
+            let submitted = order_event(&pending, timestamp, OrderEventKind::Submitted);
+            self.journal.record(submitted.with_reason("margin call"));
+            match self.execute_trade(&mut pending, execution, 0.0) {
+                Ok(mut log) => {
+                    pending.order.fill_size = log.execution.size;
+                    let filled = order_event(&pending, timestamp, OrderEventKind::Filled)
+                        .with_price(log.execution.price)
+                        .with_reason("margin call");
+                    self.journal.record(filled);
+                    log.condition_name = "MarginCall".to_string();
+                    logs.push(log);
+                }
+                Err(reason) => {
+                    let rejected = order_event(&pending, timestamp, OrderEventKind::Rejected);
+                    self.journal.record(rejected.with_reason(format!("margin call: {}", reason)));
+                    break;
+                }
+            }
+        }
+        logs
+    }
+
+    /// Value the account at the last seen prices
+    pub fn snapshot(&self, timestamp: i64) -> EquitySnapshot {
+        let account = self.account();
+        EquitySnapshot {
+            timestamp,
+            cash: account.cash,
+            equity: account.equity,
+            unrealized_pnl: self.unrealized_pnl(),
+            long_exposure: account.long_market_value,
+            short_exposure: account.short_market_value,
+        }
+    }
+
+    /// Append the current account state to `equity_curve`
+    pub fn record_equity(&mut self, timestamp: i64) {
+        let snapshot = self.snapshot(timestamp);
+        self.equity_curve.record(snapshot);
+    }
+
+    /// Snapshot at `execution.timestamp` with `ticker` valued at the fill price, taken
+    /// before and after a back-dated fill to restate the snapshots recorded since
+    fn snapshot_at_fill(&mut self, ticker: &str, execution: Execution) -> EquitySnapshot {
+        let last = self.last_prices.insert(ticker.to_string(), execution.price);
+        let snapshot = self.snapshot(execution.timestamp);
+        match last {
+            Some(price) => self.last_prices.insert(ticker.to_string(), price),
+            None => self.last_prices.remove(ticker),
+        };
+        snapshot
+    }
+
+    /// Settle market-on-close orders still waiting for a later bar once the data ends
+    ///
+    /// They fill at the close of the last regular-session bar seen, back-dated to it, or
+    /// are cancelled when there was none; `timestamp` is the last bar's, which the journal
+    /// records them at. Returns the fill logs.
+    pub fn settle_close_auctions(&mut self, timestamp: i64) -> Vec<TradeLog> {
+        let mut logs = Vec::new();
+        let mut remaining_orders = VecDeque::with_capacity(self.pending_orders.len());
+        while let Some(mut pending) = self.pending_orders.pop_front() {
+            let Some(execution) = pending.order.settle_close_auction() else {
+                if pending.order.completed {
+                    self.record_cancellation(&pending, timestamp);
+                } else {
+                    remaining_orders.push_back(pending);
+                }
+                continue;
+            };
+            let before = self.snapshot_at_fill(&pending.ticker, execution);
+            match self.execute_trade(&mut pending, execution, 0.0) {
+                Ok(log) => {
+                    let filled = order_event(&pending, timestamp, OrderEventKind::Filled).with_price(execution.price);
+                    self.journal.record(filled);
+                    let after = self.snapshot_at_fill(&pending.ticker, execution);
+                    self.equity_curve.restate(&before, &after, i64::MAX);
+                    logs.push(log);
+                }
+                Err(reason) => {
+                    let rejected = order_event(&pending, timestamp, OrderEventKind::Rejected)
+                        .with_price(execution.price)
+                        .with_reason(format!("fill not executed: {}", reason));
+                    self.journal.record(rejected);
+                    pending.order.cancel(CancelReason::NotExecuted);
+                    self.record_cancellation(&pending, timestamp);
+                }
+            }
+        }
+        self.pending_orders = remaining_orders;
+        logs
+    }
+
+    /// Process a new signal, potentially creating pending orders
+    ///
+    /// Returns the ids of the orders it queued, for the strategy to cancel or replace them
+    /// later. Cancel and replace signals queue nothing and return no ids.
     pub fn process_signal(
         &mut self, 
         signal: &Signal, 
         price: f64, 
         timestamp: i64, 
+        atr: Option<f64>,
         indicator_values: &HashMap<String, f64>,
         strategy_name: &str,
-    ) {
-        // Determine Action and create Order
-        match &signal.signal_type {
-            SignalType::Trigger(order_type) => {
-                let is_buy = order_type.is_buy();
-                let is_sell = order_type.is_sell();
-
-                // Determine OrderAction based on current position state
-                // Simplification: 
-                // - If we have a position and receive opposite signal -> Close
-                // - If we have no position and receive entry signal -> Open
-                // - If we have position and receive same signal -> Ignore (or add size, but let's stick to 1 pos per ticker)
-                
-                let maybe_pos = self.open_positions.get(&signal.ticker);
-                let (action, side) = match maybe_pos {
-                    Some(pos) => {
-                        if (is_buy && matches!(pos.side, Side::Short)) || (is_sell && matches!(pos.side, Side::Long)) {
-                            (OrderAction::Close, pos.side.clone()) // Closing the existing side
-                        } else {
-                            return; // Signal matches current position or invalid
-                        }
-                    },
-                    None => {
-                        if is_buy {
-                            (OrderAction::Open, Side::Long)
-                        } else if is_sell {
-                            (OrderAction::Open, Side::Short)
-                        } else {
-                            return;
-                        }
-                    }
+    ) -> Vec<OrderId> {
+        let legs = match &signal.signal_type {
+            SignalType::Trigger(order_type) => vec![(*order_type, OrderLinks::default())],
+            SignalType::Group(OrderGroup::OCO(orders)) => {
+                let links = OrderLinks { oco_group: Some(self.new_group_id()), ..OrderLinks::default() };
+                orders.iter().map(|order_type| (*order_type, links.clone())).collect()
+            }
+            SignalType::Group(OrderGroup::OTO { entry, then }) => {
+                vec![(*entry, OrderLinks { triggers: then.clone(), ..OrderLinks::default() })]
+            }
+            SignalType::Group(OrderGroup::Bracket { entry, stop_loss, take_profit }) => {
+                let links = OrderLinks {
+                    triggers: vec![*stop_loss, *take_profit],
+                    triggers_oco: true,
+                    ..OrderLinks::default()
                 };
+                vec![(*entry, links)]
+            }
+            SignalType::Cancel(id) => {
+                self.request(signal, *id, OrderRequest::Cancel, timestamp, strategy_name);
+                return Vec::new();
+            }
+            SignalType::Replace(id, replace) => {
+                let request = OrderRequest::Replace { replace: replace.clone(), price, atr, timestamp };
+                self.request(signal, *id, request, timestamp, strategy_name);
+                return Vec::new();
+            }
+            SignalType::Value(_) => return Vec::new(), // Ignore value signals for now
+        };
+        legs.into_iter()
+            .filter_map(|leg| self.submit(signal, leg, (price, atr), timestamp, indicator_values, strategy_name))
+            .collect()
+    }
 
-                // Create the Order object
-                let size = config::get_config().sizing_strategy.calculate(price, self.buying_power, Some(signal));
-                // If closing, use position size
-                let order_size = if let OrderAction::Close = action {
-                    if let Some(pos) = maybe_pos { pos.size } else { size }
-                } else {
-                    size
+    /// Attach a cancel or replace request to the working order `id` of the signal's ticker
+    ///
+    /// A later request replaces an earlier one on the same bar, except that a cancel stays.
+    fn request(&mut self, signal: &Signal, id: OrderId, request: OrderRequest, timestamp: i64, strategy_name: &str) {
+        let pending = self.pending_orders
+            .iter_mut()
+            .find(|pending| pending.id == id && pending.ticker == signal.ticker);
+        match pending {
+            Some(pending) => {
+                if !matches!(pending.request, Some(OrderRequest::Cancel)) {
+                    pending.request = Some(request);
+                }
+            }
+            None => {
+                // Show the order as it last was, if it ever was for this ticker
+                let event = match self.journal.last(id) {
+                    Some(last) if last.ticker == signal.ticker => OrderEvent { timestamp, reason: String::new(), price: None, ..last.clone() },
+                    _ => OrderEvent::new(timestamp, id, OrderEventKind::Rejected, &signal.ticker, strategy_name),
                 };
+                let reason = format!("order {} is not working for {}", id, signal.ticker);
+                self.refuse_request(event, request.kind(), reason);
+            }
+        }
+    }
 
-                let order_res = Order::new(
-                    *order_type,
-                    action.clone(),
-                    timestamp,
-                    None, // Default good_until
-                    order_size
-                );
-
-                if let Ok(order) = order_res {
-                    // Check replacement strategy for NEW OPEN orders
-                    if let OrderAction::Open = action {
-                         // Estimate cost (Market orders use current price)
-                         // Limit orders we might use limit price
-                         // For now use current price as estimate
-                         let estimated_cost = price * order_size as f64;
-                         
-                         if estimated_cost > self.buying_power {
-                            self.handle_replacement_strategy(
-                                PendingOrder {
-                                    order,
-                                    ticker: signal.ticker.clone(),
-                                    strategy_name: strategy_name.to_string(),
-                                    indicator_values: indicator_values.clone(),
-                                }
-                            );
-                            return;
-                         }
-                    }
-                    
-                    // Add to pending
-                    self.pending_orders.push_back(PendingOrder {
-                        order,
-                        ticker: signal.ticker.clone(),
-                        strategy_name: strategy_name.to_string(),
-                        indicator_values: indicator_values.clone(),
-                    });
+    /// Orders of `ticker` still working, oldest first
+    pub fn working_orders(&self, ticker: &str) -> Vec<(OrderId, Order)> {
+        self.pending_orders
+            .iter()
+            .filter(|pending| pending.ticker == ticker)
+            .map(|pending| (pending.id, pending.order.clone()))
+            .collect()
+    }
+
+    fn new_order_id(&mut self) -> OrderId {
+        self.next_order_id += 1;
+        OrderId(self.next_order_id)
+    }
+
+    fn new_group_id(&mut self) -> u64 {
+        self.next_group_id += 1;
+        self.next_group_id
+    }
+
+    /// Turn one order of a signal and its links into a pending order, unless it is refused
+    ///
+    /// The order's prices are resolved around `price`, with `atr` for ATR-based distances.
+    /// Returns the order's id if it was queued.
+    fn submit(
+        &mut self,
+        signal: &Signal,
+        (order_type, links): (OrderType, OrderLinks),
+        (price, atr): (f64, Option<f64>),
+        timestamp: i64,
+        indicator_values: &HashMap<String, f64>,
+        strategy_name: &str,
+    ) -> Option<OrderId> {
+        let is_buy = order_type.is_buy();
+        let is_sell = order_type.is_sell();
+
+        // Size the order; closing orders cover the position
+        let size = self.config.sizing_strategy.calculate(price, self.account().equity, Some(signal));
+        let maybe_pos = self.open_positions.get(&signal.ticker);
+        let order_size = maybe_pos.map_or(size, |pos| pos.size);
+        let id = self.new_order_id();
+        let submitted = OrderEvent::new(timestamp, id, OrderEventKind::Submitted, &signal.ticker, strategy_name)
+            .with_order_type(order_type, order_size);
+        self.journal.record(submitted);
+
+        // Determine OrderAction based on current position state
+        // Simplification: 
+        // - If we have a position and receive opposite signal -> Close
+        // - If we have no position and receive entry signal -> Open
+        // - If we have position and receive same signal -> Reject (or add size, but let's stick to 1 pos per ticker)
+        
+        let maybe_pos = self.open_positions.get(&signal.ticker);
+        let (action, side) = match maybe_pos {
+            Some(pos) => {
+                if (is_buy && matches!(pos.side, Side::Short)) || (is_sell && matches!(pos.side, Side::Long)) {
+                    (OrderAction::Close, pos.side.clone()) // Closing the existing side
+                } else {
+                    let reason = format!("{} already has a position on that side", signal.ticker);
+                    self.reject(timestamp, id, &signal.ticker, strategy_name, reason);
+                    return None;
                 }
             },
-            SignalType::Value(_) => {} // Ignore value signals for now
+            None => {
+                if is_buy {
+                    (OrderAction::Open, Side::Long)
+                } else if is_sell {
+                    (OrderAction::Open, Side::Short)
+                } else {
+                    self.reject(timestamp, id, &signal.ticker, strategy_name, "order has no side".to_string());
+                    return None;
+                }
+            }
+        };
+
+        // Shorts need a locate
+        if let (OrderAction::Open, Side::Short) = (&action, &side) {
+            if let Err(e) = self.config.borrow.locate(&signal.ticker) {
+                self.reject(timestamp, id, &signal.ticker, strategy_name, e.to_string());
+                return None;
+            }
+        }
+
+        // Auction orders must make their cutoff
+        if let Err(reason) = self.config.execution.auction.check_submission(&order_type, timestamp, &self.config.market_hours) {
+            self.reject(timestamp, id, &signal.ticker, strategy_name, reason);
+            return None;
         }
+
+        let order_res = Order::new(
+            order_type,
+            action,
+            timestamp,
+            signal.good_until, // EOD unless the signal says otherwise
+            order_size
+        ).and_then(|mut order| {
+            order.repeg = signal.repeg;
+            order.resolve_prices(price, atr)?;
+            Ok(order)
+        });
+
+        let order = match order_res {
+            Ok(order) => order,
+            Err(e) => {
+                self.reject(timestamp, id, &signal.ticker, strategy_name, e.to_string());
+                return None;
+            }
+        };
+        let pending = PendingOrder {
+            id,
+            order,
+            ticker: signal.ticker.clone(),
+            strategy_name: strategy_name.to_string(),
+            indicator_values: indicator_values.clone(),
+            delay: self.config.execution.timing.delay(),
+            links,
+            request: None,
+            commission: OrderCommission::default(),
+        };
+
+        // Check replacement strategy for NEW OPEN orders
+        if let OrderAction::Open = action {
+             // Estimate cost (Market orders use current price)
+             // Limit orders we might use limit price
+             // For now use current price as estimate
+             let estimated_cost = price * order_size as f64;
+             
+             if let Err(reason) = self.can_open(&signal.ticker, estimated_cost) {
+                self.handle_replacement_strategy(pending);
+                if !self.pending_orders.iter().any(|pending| pending.id == id) {
+                    self.reject(timestamp, id, &signal.ticker, strategy_name, reason);
+                    return None;
+                }
+                self.journal.record(OrderEvent::new(timestamp, id, OrderEventKind::Accepted, &signal.ticker, strategy_name)
+                    .with_order_type(order_type, order_size));
+                return Some(id);
+             }
+        }
+        
+        // Add to pending
+        self.journal.record(order_event(&pending, timestamp, OrderEventKind::Accepted));
+        self.pending_orders.push_back(pending);
+        Some(id)
+    }
+
+    /// Submit the orders a filled order triggers (one-triggers-other)
+    ///
+    /// Triggered orders are good til cancelled and sized like any other, so exits
+    /// cover the position as it is after the fill. Their prices are resolved around
+    /// the fill price, with the ATR the filled order was submitted with.
+    fn submit_triggered(&mut self, pending: &PendingOrder, row: &Row) {
+        if pending.links.triggers.is_empty() || pending.order.fill_size == 0 {
+            return;
+        }
+        let links = OrderLinks {
+            oco_group: pending.links.triggers_oco.then(|| self.new_group_id()),
+            ..OrderLinks::default()
+        };
+        for order_type in &pending.links.triggers {
+            let signal = Signal::new_trigger(pending.ticker.clone(), *order_type).with_good_until(OrderTimeline::GTC);
+            let price = pending.order.fill_price.unwrap_or(row.close);
+            self.submit(&signal, (*order_type, links.clone()), (price, pending.order.atr), row.timestamp, &pending.indicator_values, &pending.strategy_name);
+        }
+    }
+
+    /// Record an order that stopped working before it filled in full
+    fn record_cancellation(&mut self, pending: &PendingOrder, timestamp: i64) {
+        let Some(reason) = pending.order.cancelled else {
+            return;
+        };
+        let kind = match reason {
+            CancelReason::Expired => OrderEventKind::Expired,
+            _ => OrderEventKind::Cancelled,
+        };
+        self.journal.record(order_event(pending, timestamp, kind).with_reason(reason.to_string()));
+        self.cancellations.push(OrderCancellation {
+            timestamp,
+            ticker: pending.ticker.clone(),
+            strategy_name: pending.strategy_name.clone(),
+            reason,
+            filled: pending.order.fill_size,
+            unfilled: pending.order.size - pending.order.fill_size,
+        });
+    }
+
+    /// Record an order refused before reaching the queue
+    fn reject(&mut self, timestamp: i64, id: OrderId, ticker: &str, strategy_name: &str, reason: String) {
+        self.journal.record(OrderEvent::new(timestamp, id, OrderEventKind::Rejected, ticker, strategy_name).with_reason(reason.clone()));
+        self.rejections.push(OrderRejection {
+            timestamp,
+            order_id: id,
+            ticker: ticker.to_string(),
+            strategy_name: strategy_name.to_string(),
+            reason,
+        });
+    }
+
+    /// Record a cancel or replace request that was not carried out
+    ///
+    /// `event` describes the order the request was for; its kind is set from `request`.
+    fn refuse_request(&mut self, event: OrderEvent, request: RequestKind, reason: String) {
+        self.refused_requests.push(RefusedRequest {
+            timestamp: event.timestamp,
+            order_id: event.order_id,
+            ticker: event.ticker.clone(),
+            strategy_name: event.strategy_name.clone(),
+            request,
+            reason: reason.clone(),
+        });
+        let kind = match request {
+            RequestKind::Cancel => OrderEventKind::CancelRejected,
+            RequestKind::Replace => OrderEventKind::ReplaceRejected,
+        };
+        self.journal.record(OrderEvent { kind, ..event }.with_reason(reason));
+    }
+
+    /// Queue an order the portfolio submits itself ahead of all others
+    fn queue_first(&mut self, pending: PendingOrder) {
+        let timestamp = pending.order.timestamp;
+        self.journal.record(order_event(&pending, timestamp, OrderEventKind::Submitted));
+        self.journal.record(order_event(&pending, timestamp, OrderEventKind::Accepted));
+        self.pending_orders.push_front(pending);
     }
 
     fn handle_replacement_strategy(&mut self, pending: PendingOrder) {
-        let strategy = config::get_config().replacement_strategy;
-        match strategy {
+        match self.config.replacement_strategy {
             ReplacementStrategy::Cancel => {
-                // Drop the order
-                println!("Insufficient BP for {}. Order Cancelled.", pending.ticker);
+                // Drop the order; `submit` records the rejection
             },
             ReplacementStrategy::Queue => {
                 // Add to queue anyway. It will be checked in check_orders each tick
-                println!("Insufficient BP for {}. Order Queued.", pending.ticker);
                 self.pending_orders.push_back(pending);
             },
             ReplacementStrategy::ReplaceOldest => {
@@ -170,22 +718,25 @@ impl Portfolio {
                              pos.size
                         ) {
                             // Push close order to front to be processed first
-                            self.pending_orders.push_front(PendingOrder {
+                            let close = PendingOrder {
+                                id: self.new_order_id(),
                                 order: close_order,
                                 ticker: oldest_ticker.clone(),
                                 strategy_name: "Replacement".to_string(),
                                 indicator_values: HashMap::new(),
-                            });
+                                delay: self.config.execution.timing.delay(),
+                                links: OrderLinks::default(),
+                                request: None,
+                                commission: OrderCommission::default(),
+                            };
+                            self.queue_first(close);
                             
                             // Queue new order at back
-                            println!("ReplaceOldest triggered: Closing {} for {}", oldest_ticker, pending.ticker);
                             self.pending_orders.push_back(pending);
                         }
                     }
-                } else {
-                     // No positions to replace
-                     println!("Insufficient BP for {}. No positions to replace.", pending.ticker);
                 }
+                // With no positions to replace, `submit` records the rejection
             },
              ReplacementStrategy::ReplaceNewest => {
                 if let Some(newest_ticker) = self.get_newest_position_ticker() {
@@ -202,21 +753,26 @@ impl Portfolio {
                              None,
                              pos.size
                         ) {
-                             self.pending_orders.push_front(PendingOrder {
+                             // Push close order to front to be processed first
+                             let close = PendingOrder {
+                                id: self.new_order_id(),
                                 order: close_order,
                                 ticker: newest_ticker.clone(),
                                 strategy_name: "Replacement".to_string(),
                                 indicator_values: HashMap::new(),
-                            });
-                            println!("ReplaceNewest triggered: Closing {} for {}", newest_ticker, pending.ticker);
+                                delay: self.config.execution.timing.delay(),
+                                links: OrderLinks::default(),
+                                request: None,
+                                commission: OrderCommission::default(),
+                             };
+                             self.queue_first(close);
                             self.pending_orders.push_back(pending);
                         }
                      }
                 }
             },
             ReplacementStrategy::ReplaceSignal => {
-                 // Needs signal comparison logic. For now, behave like Cancel or Queue
-                 println!("ReplaceSignal not fully implemented. Queuing.");
+                 // Needs signal comparison logic. For now, behave like Queue
                  self.pending_orders.push_back(pending);
             }
         }
@@ -236,6 +792,10 @@ impl Portfolio {
 
     /// Check all pending orders against current market data
     /// Returns any generated TradeLogs
+    ///
+    /// A market-on-close fill is only known once the bar after the session's last one
+    /// arrives, and is back-dated to that last bar; the equity snapshots recorded since
+    /// that bar are restated to include it.
     pub fn check_orders(&mut self, row: &Row) -> Vec<TradeLog> {
         let mut logs = Vec::new();
         let mut remaining_orders = VecDeque::new();
@@ -255,116 +815,735 @@ impl Portfolio {
         // Optimization: Use separate queues or index?
         // For now, simple iteration.
         
+        self.impact.update(row);
+
+        let mut filled = Vec::new();
+        // Completed orders whose triggered orders are submitted once this bar is done
+        let mut finished = Vec::new();
         while let Some(mut pending) = self.pending_orders.pop_front() {
             if pending.ticker != row.ticker {
                 remaining_orders.push_back(pending);
                 continue;
             }
+            if pending.delay > 0 {
+                pending.delay -= 1;
+                remaining_orders.push_back(pending);
+                continue;
+            }
 
             // Check order
-            if let Ok(_) = pending.order.check(row) {
+            let at_market = pending.order.fills_at_market();
+            match pending.order.check(row, &self.config.market_hours, &self.config.execution) {
+                Ok(Some(execution)) => filled.push((pending, execution, at_market)),
+                // Not filled, but still active
+                Ok(None) if !pending.order.completed => remaining_orders.push_back(pending),
+                // Expired or cancelled, after filling in part if at all
+                Ok(None) => {
+                    self.record_cancellation(&pending, row.timestamp);
+                    finished.push(pending);
+                }
+                // Can't be priced (e.g. an ATR distance without an ATR), so it never could
+                Err(e) => {
+                    let rejected = order_event(&pending, row.timestamp, OrderEventKind::Rejected).with_reason(e.to_string());
+                    self.journal.record(rejected);
+                    pending.order.cancel(CancelReason::Invalid);
+                    self.record_cancellation(&pending, row.timestamp);
+                }
+            }
+        }
+
+        // Orders filling on the same bar execute in the order the intrabar path reaches
+        // their prices, so e.g. only one of a stop-loss and a target closes the position
+        let path = self.config.execution.intrabar_path;
+        let timing = self.config.execution.timing;
+        // Fills at the timing's market price (market orders and stop-markets triggered on an
+        // earlier bar) come after the path, unless that price is the open
+        let leg = |pending: &PendingOrder, price: f64, at_market: bool| match pending.order.order_type {
+            _ if at_market && (price != row.open || timing.delay() == 0) => 3,
+            OrderType::AuctionClose(_) => 3,
+            _ => path.leg(row, price, pending.order.is_long()),
+        };
+        filled.sort_by_key(|(pending, execution, at_market)| leg(pending, execution.price, *at_market));
+        // The path only decides between orders that compete, i.e. legs of one OCO group
+        let group = |pending: &PendingOrder| pending.links.oco_group.ok_or(pending.id);
+        let groups_on = |n| filled.iter().filter(|(p, e, m)| leg(p, e.price, *m) == n).map(|(p, _, _)| group(p)).collect::<HashSet<_>>();
+        let path_decides: HashSet<_> = groups_on(1).intersection(&groups_on(2)).copied().collect();
+
+        // A leg that completes cancels its OCO siblings; a partial fill takes its shares
+        // off them, so e.g. a bracket's stop keeps covering what is left of the position
+        let mut oco_filled = HashSet::new();
+        let mut oco_partial: Vec<(u64, OrderId, i64)> = Vec::new();
+        for (mut pending, execution, _) in filled {
+            if pending.links.oco_group.is_some_and(|group| oco_filled.contains(&group)) {
+                pending.order.cancel(CancelReason::OcoSibling);
+                self.record_cancellation(&pending, row.timestamp);
+                continue;
+            }
+            let ambiguous = path_decides.contains(&group(&pending)) || pending.order.ambiguous;
+            // Limit prices are guaranteed, only orders filling at market slip
+            let order_type = pending.order.order_type;
+            let (price, slippage) = if order_type.is_marketable() {
+                self.slippage.apply(execution.price, order_type.is_buy(), row)
+            } else {
+                (execution.price, 0.0)
+            };
+
+            // Execute Trade
+            let backdated = (execution.timestamp < row.timestamp).then(|| self.snapshot_at_fill(&pending.ticker, execution));
+            let log = match self.execute_trade(&mut pending, Execution { price, ..execution }, slippage) {
+                Ok(log) => log,
+                Err(reason) => {
+                    // Execution failed (e.g. BP check for Open order in Queue, or the
+                    // position was already closed by an order earlier on the bar).
+                    // The order is dropped along with any unfilled remainder.
+                    let rejected = order_event(&pending, row.timestamp, OrderEventKind::Rejected)
+                        .with_price(price)
+                        .with_reason(format!("fill not executed: {}", reason));
+                    self.journal.record(rejected);
+                    pending.order.cancel(CancelReason::NotExecuted);
+                    self.record_cancellation(&pending, row.timestamp);
+                    continue;
+                }
+            };
+            if ambiguous {
+                self.ambiguous_fills += 1;
+            }
+            let kind = if pending.order.fill_size < pending.order.size {
+                OrderEventKind::PartiallyFilled
+            } else {
+                OrderEventKind::Filled
+            };
+            self.journal.record(order_event(&pending, row.timestamp, kind).with_price(log.execution.price));
+            if let Some(before) = backdated {
+                let after = self.snapshot_at_fill(&pending.ticker, execution);
+                self.equity_curve.restate(&before, &after, row.timestamp);
+            }
+            if let Some(group) = pending.links.oco_group {
                 if pending.order.completed {
-                     // Order Filled
-                     if let Some(fill_price) = pending.order.fill_price {
-                         // Execute Trade
-                         if let Some(log) = self.execute_trade(pending, fill_price) {
-                             logs.push(log);
-                         } else {
-                             // Execution failed (e.g. BP check for Open order in Queue)
-                             // If it was Open and failed BP, maybe keep in queue?
-                             // But check() already marked it completed. We'd need to reset or recreate.
-                             // For simplicity: If execution fails due to BP, we drop it (or log error).
-                         }
-                     }
+                    oco_filled.insert(group);
                 } else {
-                    // Not filled, but still active
-                    remaining_orders.push_back(pending);
+                    oco_partial.push((group, pending.id, log.execution.size));
                 }
+            }
+            logs.push(log);
+            // Partially filled orders wait for more volume on later bars,
+            // unless their time in force cancelled the rest (IOC)
+            if !pending.order.completed {
+                remaining_orders.push_back(pending);
             } else {
-                 // Error checking order (e.g. expired?)
-                 if pending.order.completed {
-                     // Expired or cancelled
-                 } else {
-                     remaining_orders.push_back(pending);
-                 }
+                if let (Some(request), None) = (&pending.request, pending.order.cancelled) {
+                    let reason = format!("order {} filled first", pending.id);
+                    let event = order_event(&pending, row.timestamp, OrderEventKind::Rejected);
+                    self.refuse_request(event, request.kind(), reason);
+                }
+                self.record_cancellation(&pending, row.timestamp);
+                finished.push(pending);
             }
         }
         
+        // The bar's return only counts towards slippage once its own fills are priced
+        self.slippage.update(row);
+
+        // Siblings of filled OCO orders that didn't fill on this bar
+        let (cancelled, remaining_orders): (VecDeque<_>, VecDeque<_>) = remaining_orders
+            .into_iter()
+            .partition(|pending| pending.links.oco_group.is_some_and(|group| oco_filled.contains(&group)));
+        for mut pending in cancelled {
+            pending.order.cancel(CancelReason::OcoSibling);
+            self.record_cancellation(&pending, row.timestamp);
+        }
+        let mut working = VecDeque::with_capacity(remaining_orders.len());
+        for mut pending in remaining_orders {
+            let shares: i64 = oco_partial
+                .iter()
+                .filter(|(group, id, _)| pending.links.oco_group == Some(*group) && *id != pending.id)
+                .map(|(_, _, size)| size)
+                .sum();
+            if shares == 0 {
+                working.push_back(pending);
+                continue;
+            }
+            pending.order.reduce(shares, CancelReason::OcoSibling);
+            if pending.order.completed {
+                self.record_cancellation(&pending, row.timestamp);
+                finished.push(pending);
+            } else {
+                let reason = format!("OCO sibling filled {} shares", shares);
+                self.journal.record(order_event(&pending, row.timestamp, OrderEventKind::Replaced).with_reason(reason));
+                working.push_back(pending);
+            }
+        }
+        let remaining_orders = working;
+
+        // Cancel and replace requests for this ticker, now that the bar they came on is checked
+        let mut working = VecDeque::with_capacity(remaining_orders.len());
+        for mut pending in remaining_orders {
+            let request = match pending.request.take() {
+                Some(request) if pending.ticker == row.ticker => request,
+                request => {
+                    pending.request = request;
+                    working.push_back(pending);
+                    continue;
+                }
+            };
+            match request {
+                OrderRequest::Cancel => {
+                    pending.order.cancel(CancelReason::Requested);
+                    self.record_cancellation(&pending, row.timestamp);
+                    finished.push(pending);
+                }
+                OrderRequest::Replace { replace, price, atr, timestamp } => {
+                    match pending.order.replace(&replace, price, atr, timestamp) {
+                        Ok(()) => self.journal.record(order_event(&pending, row.timestamp, OrderEventKind::Replaced)),
+                        Err(e) => {
+                            let reason = format!("replace of order {} refused: {}", pending.id, e);
+                            let event = order_event(&pending, row.timestamp, OrderEventKind::Rejected);
+                            self.refuse_request(event, RequestKind::Replace, reason);
+                        }
+                    }
+                    working.push_back(pending);
+                }
+            }
+        }
+        let mut remaining_orders = working;
+
+        for pending in &finished {
+            self.submit_triggered(pending, row);
+        }
+        // Orders submitted while checking (triggered or replacement orders) come after the rest
+        remaining_orders.append(&mut self.pending_orders);
         self.pending_orders = remaining_orders;
         logs
     }
 
-    fn execute_trade(&mut self, pending: PendingOrder, fill_price: f64) -> Option<TradeLog> {
-         let size = pending.order.fill_size; // should use fill_size
+    /// Apply one fill of an order to the positions and cash
+    ///
+    /// `slippage` is per share and already included in `execution.price`; market impact
+    /// is added here for orders filling at market, based on the fill's size. Opening
+    /// fills add to a position of the same side; closing fills smaller than the
+    /// position split the closed shares off into their own closed position.
+    /// Returns why the fill could not be applied, if it couldn't.
+    fn execute_trade(&mut self, pending: &mut PendingOrder, execution: Execution, slippage: f64) -> Result<TradeLog, String> {
+         let order_type = pending.order.order_type;
+         let (price, impact) = if order_type.is_marketable() {
+             self.impact.apply(&pending.ticker, execution.price, execution.size, order_type.is_buy())
+         } else {
+             (execution.price, 0.0)
+         };
+         let execution = Execution { price, ..execution };
+         let Execution { timestamp, price, size } = execution;
          
          match pending.order.open_or_close {
              OrderAction::Open => {
-                 let cost = fill_price * size as f64;
-                 if self.buying_power < cost {
-                     println!("Order filled but insufficient BP for execution: {}", pending.ticker);
-                     return None;
-                 }
+                 let cost = price * size as f64;
+                 self.can_open(&pending.ticker, cost)?;
                  
-                 self.buying_power -= cost;
-                 let id = Uuid::new_v4().to_string();
-                 let pos = Position::new(
-                     id,
-                     pending.ticker.clone(),
-                     // Infer side from OrderType. 
-                     // MarketBuy -> Long, MarketSell -> Short (for Open)
-                     if pending.order.order_type.is_buy() { Side::Long } else { Side::Short },
-                     size,
-                     fill_price,
-                     pending.order.timestamp
-                 );
-                 
-                 self.open_positions.insert(pending.ticker.clone(), pos.clone());
+                 // Infer side from OrderType.
+                 // MarketBuy -> Long, MarketSell -> Short (for Open)
+                 let side = if pending.order.order_type.is_buy() { Side::Long } else { Side::Short };
+                 // An entry queued before an earlier entry filled can meet the opposite position
+                 let same_side = |pos: &Position| std::mem::discriminant(&pos.side) == std::mem::discriminant(&side);
+                 if self.open_positions.get(&pending.ticker).is_some_and(|pos| !same_side(pos)) {
+                     return Err(format!("{} already has an opposite position", pending.ticker));
+                 }
+                 let fees = self.fees.charge(price, size, matches!(side, Side::Short), timestamp, &mut pending.commission);
+                 match side {
+                     Side::Short => self.cash += cost, // short sale proceeds
+                     _ => self.cash -= cost,
+                 }
+                 self.cash -= fees.total();
+
+                 let pos = match self.open_positions.get_mut(&pending.ticker) {
+                     // Another fill of the order (or a second entry): average into the position
+                     Some(pos) => {
+                         pos.entry_price = (pos.entry_price * pos.size as f64 + cost) / (pos.size + size) as f64;
+                         pos.size += size;
+                         pos.fees += fees.total();
+                         pos.clone()
+                     }
+                     None => {
+                         let id = Uuid::new_v4().to_string();
+                         let mut pos = Position::new(
+                             id,
+                             pending.ticker.clone(),
+                             side,
+                             size,
+                             price,
+                             timestamp
+                         );
+                         pos.fees = fees.total();
+                         self.open_positions.insert(pending.ticker.clone(), pos.clone());
+                         pos
+                     }
+                 };
                  
-                 Some(TradeLog::new(
+                 Ok(TradeLog::new(
                      pos,
                      Action::Entry,
-                     pending.strategy_name,
+                     pending.strategy_name.clone(),
                      "OrderFilled".to_string(),
-                     pending.indicator_values
-                 ))
+                     pending.indicator_values.clone()
+                 ).with_execution(execution)
+                   .with_slippage(slippage * size as f64)
+                   .with_impact(impact * size as f64)
+                   .with_fees(fees))
              },
              OrderAction::Close => {
-                 if let Some(mut pos) = self.open_positions.remove(&pending.ticker) {
-                     // Force close logic on position struct
-                     if let Ok(_) = pos.close(fill_price, pending.order.timestamp) {
-                          // Update BP
-                        match pos.side {
-                            Side::Short => {
-                                let cost = fill_price * pos.size as f64;
-                                self.buying_power -= cost; // Short exit you pay back
-                                // Wait, simple cash model:
-                                // Short Open: BP += Proceeds. 
-                                // Short Close: BP -= Cost.
-                                // Correct.
-                            }
-                            Side::Long => {
-                                let proceeds = fill_price * pos.size as f64;
-                                self.buying_power += proceeds;
-                            }
-                            _ => {}
-                        }
-                        
-                        let log = TradeLog::new(
-                             pos.clone(),
-                             Action::Exit,
-                             pending.strategy_name,
-                             "OrderFilled".to_string(),
-                             pending.indicator_values
-                         );
-                         self.closed_positions.push(pos);
-                         Some(log)
-                     } else {
-                         None
-                     }
+                 let no_position = || format!("{} has no position to close", pending.ticker);
+                 let open_size = self.open_positions.get(&pending.ticker).ok_or_else(no_position)?.size;
+                 let size = size.min(open_size);
+                 let mut pos = if size < open_size {
+                     // Split off the closed shares with their share of the costs so far
+                     let open = self.open_positions.get_mut(&pending.ticker).ok_or_else(no_position)?;
+                     let fraction = size as f64 / open_size as f64;
+                     let mut part = open.clone();
+                     part.size = size;
+                     part.fees = open.fees * fraction;
+                     part.borrow_fees = open.borrow_fees * fraction;
+                     open.size -= size;
+                     open.fees -= part.fees;
+                     open.borrow_fees -= part.borrow_fees;
+                     part
                  } else {
-                     None
+                     self.open_positions.remove(&pending.ticker).ok_or_else(no_position)?
+                 };
+                 pos.close(price, timestamp).map_err(|e| e.to_string())?;
+
+                 // Short exits pay back the shares, long exits receive the proceeds
+                 match pos.side {
+                     Side::Short => self.cash -= price * size as f64,
+                     Side::Long => self.cash += price * size as f64,
+                     _ => {}
                  }
+                 let fees = self.fees.charge(price, size, matches!(pos.side, Side::Long), timestamp, &mut pending.commission);
+                 self.cash -= fees.total();
+                 pos.fees += fees.total();
+                 
+                 let log = TradeLog::new(
+                     pos.clone(),
+                     Action::Exit,
+                     pending.strategy_name.clone(),
+                     "OrderFilled".to_string(),
+                     pending.indicator_values.clone()
+                 ).with_execution(Execution { size, ..execution })
+                   .with_slippage(slippage * size as f64)
+                   .with_impact(impact * size as f64)
+                   .with_fees(fees);
+                 self.closed_positions.push(pos);
+                 Ok(log)
              }
          }
     }
 }
+
+/// Event of `kind` for a queued order, with its current type, size and fills
+fn order_event(pending: &PendingOrder, timestamp: i64, kind: OrderEventKind) -> OrderEvent {
+    OrderEvent::new(timestamp, pending.id, kind, &pending.ticker, &pending.strategy_name).with_order(&pending.order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtest::margin::MarginConfig;
+    use crate::backtest::execution::{ExecutionConfig, ExecutionTiming, IntrabarPath};
+    use crate::position::order::{OrderDistance, OrderSide, OrderTimeline};
+    use crate::backtest::slippage::SlippageModel;
+
+    #[test]
+    fn test_snapshot_marks_positions_to_market() {
+        let mut portfolio = Portfolio::new(Config::default());
+        portfolio.cash = 10_000.0;
+        portfolio.open_positions.insert("AAPL".into(), Position::new("a".into(), "AAPL".into(), Side::Long, 10, 100.0, 0));
+        portfolio.open_positions.insert("MSFT".into(), Position::new("m".into(), "MSFT".into(), Side::Short, 5, 200.0, 0));
+        portfolio.update_prices("AAPL", 110.0);
+        portfolio.update_prices("MSFT", 190.0);
+
+        let snapshot = portfolio.snapshot(1);
+        assert_eq!(snapshot.long_exposure, 1100.0);
+        assert_eq!(snapshot.short_exposure, 950.0);
+        assert_eq!(snapshot.unrealized_pnl, 100.0 + 50.0);
+        assert_eq!(snapshot.equity, 10_000.0 + 1100.0 - 950.0);
+
+        portfolio.record_equity(1);
+        portfolio.update_prices("AAPL", 120.0);
+        portfolio.record_equity(1);
+        assert_eq!(portfolio.equity_curve.bars().len(), 1);
+        assert_eq!(portfolio.equity_curve.last().unwrap().long_exposure, 1200.0);
+    }
+
+    #[test]
+    fn test_margin_limits_and_liquidation() {
+        let config = Config {
+            starting_buying_power: 10_000.0,
+            margin: MarginConfig { initial: 0.5, maintenance: 0.25, ..MarginConfig::default() },
+            ..Config::default()
+        };
+        let mut portfolio = Portfolio::new(config);
+
+        // $20k long on $10k equity uses all the Reg-T buying power
+        portfolio.update_prices("AAPL", 100.0);
+        assert_eq!(portfolio.buying_power(), 20_000.0);
+        assert!(portfolio.can_open("AAPL", 20_000.0).is_ok());
+        assert!(portfolio.can_open("AAPL", 20_001.0).is_err());
+        portfolio.cash -= 20_000.0;
+        portfolio.open_positions.insert("AAPL".into(), Position::new("a".into(), "AAPL".into(), Side::Long, 200, 100.0, 0));
+
+        // Equity 10k -> 4k at $70, maintenance needs 0.25 * 14k = 3.5k
+        portfolio.update_prices("AAPL", 70.0);
+        assert!(portfolio.check_margin(1).is_empty());
+
+        // Equity 2k at $60, maintenance needs 3k
+        portfolio.update_prices("AAPL", 60.0);
+        let logs = portfolio.check_margin(2);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].condition_name, "MarginCall");
+        let events = portfolio.journal.events();
+        assert_eq!(events.iter().map(|e| e.kind).collect::<Vec<_>>(), vec![OrderEventKind::Submitted, OrderEventKind::Filled]);
+        assert!(events.iter().all(|e| e.reason == "margin call"));
+        assert!(portfolio.open_positions.is_empty());
+        assert_eq!(portfolio.cash, 2_000.0);
+    }
+
+    fn bar(ticker: &str, timestamp: i64, open: f64, close: f64) -> Row {
+        Row {
+            timestamp,
+            open,
+            high: open.max(close) + 1.0,
+            low: open.min(close) - 1.0,
+            close,
+            volume: 10_000,
+            ticker: ticker.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_next_bar_open_execution() {
+        let config = Config {
+            slippage: SlippageModel::None,
+            execution: ExecutionConfig { timing: ExecutionTiming::NextBarOpen, ..ExecutionConfig::default() },
+            ..Config::default()
+        };
+        let mut portfolio = Portfolio::new(config);
+        // 2021-03-01 15:00 and 15:05
+        let t0 = 1_614_610_800_000_000_000;
+        let signal = Signal::new_trigger("AAPL".into(), OrderType::MarketBuy());
+
+        let first = bar("AAPL", t0, 100.0, 101.0);
+        portfolio.process_signal(&signal, first.close, first.timestamp, None, &HashMap::new(), "test");
+        assert!(portfolio.check_orders(&first).is_empty());
+        // Other tickers' bars don't count towards the delay
+        assert!(portfolio.check_orders(&bar("MSFT", t0, 50.0, 50.0)).is_empty());
+
+        let logs = portfolio.check_orders(&bar("AAPL", t0 + 300_000_000_000, 102.0, 103.0));
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].position.entry_price, 102.0);
+    }
+
+    #[test]
+    fn test_market_on_close_fill_restates_the_close_snapshot() {
+        let mut portfolio = Portfolio::new(Config { slippage: SlippageModel::None, ..Config::default() });
+        // 2021-03-01 15:00 and 15:55, then 2021-03-02 09:30
+        let t0 = 1_614_610_800_000_000_000;
+        let minute = 60 * 1_000_000_000;
+        let last = t0 + 55 * minute;
+        let signal = Signal::new_trigger("AAPL".into(), OrderType::AuctionClose(OrderSide::Buy));
+        portfolio.process_signal(&signal, 100.0, t0, None, &HashMap::new(), "test");
+
+        // MSFT's 16:00 bar comes between AAPL's last bar and the one showing it was the last
+        for row in [bar("AAPL", t0, 100.0, 100.0), bar("AAPL", last, 100.0, 101.0), bar("MSFT", last + 5 * minute, 50.0, 50.0)] {
+            portfolio.update_prices(&row.ticker, row.close);
+            assert!(portfolio.check_orders(&row).is_empty());
+            portfolio.record_equity(row.timestamp);
+        }
+        let next_day = bar("AAPL", t0 + 1110 * minute, 95.0, 96.0);
+        portfolio.update_prices("AAPL", next_day.close);
+        let logs = portfolio.check_orders(&next_day);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].execution.timestamp, last);
+
+        let size = logs[0].execution.size as f64;
+        let restated: Vec<_> = portfolio.equity_curve.bars()[1..].iter().map(|s| (s.long_exposure, s.cash)).collect();
+        assert_eq!(restated, vec![(size * 101.0, portfolio.cash); 2]);
+        assert_eq!(portfolio.last_prices["AAPL"], 96.0);
+
+        // The data ends before the bar after the session's last one: the sell fills at the
+        // 10:00 close, and a buy submitted after it, never having seen a bar, is cancelled
+        let ten = next_day.timestamp + 30 * minute;
+        let sell = Signal::new_trigger("AAPL".into(), OrderType::AuctionClose(OrderSide::Sell));
+        let ids = portfolio.process_signal(&sell, 96.0, next_day.timestamp, None, &HashMap::new(), "test");
+        portfolio.update_prices("AAPL", 97.0);
+        assert!(portfolio.check_orders(&bar("AAPL", ten, 96.0, 97.0)).is_empty());
+        portfolio.record_equity(ten);
+        let buy = Signal::new_trigger("MSFT".into(), OrderType::AuctionClose(OrderSide::Buy));
+        portfolio.process_signal(&buy, 50.0, ten, None, &HashMap::new(), "test");
+
+        let logs = portfolio.settle_close_auctions(ten);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].position.exit_price, Some(97.0));
+        assert!(portfolio.open_positions.is_empty() && portfolio.pending_orders.is_empty());
+        assert_eq!(portfolio.journal.last(ids[0]).unwrap().kind, OrderEventKind::Filled);
+        assert_eq!(portfolio.cancellations[0].reason, CancelReason::AuctionMissed);
+        assert_eq!(portfolio.equity_curve.last().unwrap().long_exposure, 0.0);
+    }
+
+    #[test]
+    fn test_volatility_slippage_leaves_out_the_fill_bar() {
+        let config = Config {
+            slippage: SlippageModel::Volatility { multiple: 1.0, bars: 10 },
+            execution: ExecutionConfig { timing: ExecutionTiming::NextBarOpen, ..ExecutionConfig::default() },
+            ..Config::default()
+        };
+        let mut portfolio = Portfolio::new(config);
+        let signal = Signal::new_trigger("AAPL".into(), OrderType::MarketBuy());
+        for timestamp in 0..3 {
+            if timestamp == 2 {
+                portfolio.process_signal(&signal, 100.0, timestamp, None, &HashMap::new(), "test");
+            }
+            assert!(portfolio.check_orders(&bar("AAPL", timestamp, 100.0, 100.0)).is_empty());
+        }
+
+        // Filled at the open, before the bar's jump to 150 happened
+        let logs = portfolio.check_orders(&bar("AAPL", 3, 100.0, 150.0));
+        assert_eq!((logs[0].execution.price, logs[0].slippage), (100.0, 0.0));
+    }
+
+    #[test]
+    fn test_intrabar_path_decides_stop_or_target() {
+        let exit = |path: IntrabarPath, oco_group: Option<u64>| {
+            let config = Config {
+                slippage: SlippageModel::None,
+                execution: ExecutionConfig { intrabar_path: path, ..ExecutionConfig::default() },
+                ..Config::default()
+            };
+            let mut portfolio = Portfolio::new(config);
+            portfolio.open_positions.insert("AAPL".into(), Position::new("a".into(), "AAPL".into(), Side::Long, 10, 100.0, 0));
+            for order_type in [
+                OrderType::StopMarketSell(OrderDistance::Fixed(95.0)),
+                OrderType::LimitSell(OrderDistance::Fixed(105.0)),
+            ] {
+                let id = portfolio.new_order_id();
+                portfolio.pending_orders.push_back(PendingOrder {
+                    id,
+                    order: Order::new(order_type, OrderAction::Close, 0, Some(OrderTimeline::GTC), 10).unwrap(),
+                    ticker: "AAPL".into(),
+                    strategy_name: "test".into(),
+                    indicator_values: HashMap::new(),
+                    delay: 0,
+                    links: OrderLinks { oco_group, ..OrderLinks::default() },
+                    request: None,
+                    commission: OrderCommission::default(),
+                });
+            }
+            let row = Row { high: 106.0, low: 94.0, ..bar("AAPL", 1, 100.0, 100.0) };
+            let logs = portfolio.check_orders(&row);
+            assert_eq!(logs.len(), 1);
+            (logs[0].position.exit_price, portfolio.ambiguous_fills, portfolio.cancellations[0].reason)
+        };
+        assert_eq!(exit(IntrabarPath::OpenHighLowClose, Some(1)), (Some(105.0), 1, CancelReason::OcoSibling));
+        assert_eq!(exit(IntrabarPath::WorstCase, Some(1)), (Some(95.0), 1, CancelReason::OcoSibling));
+        // Unrelated orders still fill in path order, but the path didn't choose between them;
+        // the other order has no position left to close
+        assert_eq!(exit(IntrabarPath::WorstCase, None), (Some(95.0), 0, CancelReason::NotExecuted));
+    }
+
+    #[test]
+    fn test_triggered_stop_fills_after_the_path() {
+        let config = Config {
+            slippage: SlippageModel::None,
+            execution: ExecutionConfig {
+                intrabar_path: IntrabarPath::OpenLowHighClose,
+                max_participation: Some(0.001),
+                ..ExecutionConfig::default()
+            },
+            ..Config::default()
+        };
+        let mut portfolio = Portfolio::new(config);
+        portfolio.open_positions.insert("AAPL".into(), Position::new("a".into(), "AAPL".into(), Side::Long, 20, 100.0, 0));
+        for order_type in [
+            OrderType::StopMarketSell(OrderDistance::Fixed(95.0)),
+            OrderType::LimitSell(OrderDistance::Fixed(105.0)),
+        ] {
+            let id = portfolio.new_order_id();
+            portfolio.pending_orders.push_back(PendingOrder {
+                id,
+                order: Order::new(order_type, OrderAction::Close, 0, Some(OrderTimeline::GTC), 20).unwrap(),
+                ticker: "AAPL".into(),
+                strategy_name: "test".into(),
+                indicator_values: HashMap::new(),
+                delay: 0,
+                links: OrderLinks { oco_group: Some(1), ..OrderLinks::default() },
+                request: None,
+                commission: OrderCommission::default(),
+            });
+        }
+        // The stop triggers and sells the 10 shares the volume allows
+        let logs = portfolio.check_orders(&Row { high: 101.0, low: 94.0, ..bar("AAPL", 1, 100.0, 96.0) });
+        assert_eq!(logs[0].execution.size, 10);
+
+        // The rest of the stop sells at the close, after the path reached the limit
+        portfolio.pending_orders[0].order.ambiguous = true;
+        let logs = portfolio.check_orders(&Row { high: 106.0, low: 97.0, ..bar("AAPL", 2, 100.0, 98.0) });
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].position.exit_price, Some(105.0));
+        assert_eq!(portfolio.cancellations[0].reason, CancelReason::OcoSibling);
+        assert_eq!(portfolio.ambiguous_fills, 0);
+    }
+
+    #[test]
+    fn test_partial_exits_split_the_position() {
+        let config = Config {
+            slippage: SlippageModel::None,
+            execution: ExecutionConfig { max_participation: Some(0.01), ..ExecutionConfig::default() },
+            ..Config::default()
+        };
+        let mut portfolio = Portfolio::new(config);
+        let mut pos = Position::new("a".into(), "AAPL".into(), Side::Long, 300, 100.0, 0);
+        pos.fees = 3.0;
+        portfolio.open_positions.insert("AAPL".into(), pos);
+        let signal = Signal::new_trigger("AAPL".into(), OrderType::MarketSell());
+        portfolio.process_signal(&signal, 100.0, 0, None, &HashMap::new(), "test");
+
+        // 1% of 10k shares per bar
+        let logs = portfolio.check_orders(&bar("AAPL", 1, 100.0, 101.0));
+        assert_eq!(logs[0].execution.size, 100);
+        assert_eq!(logs[0].position.size, 100);
+        assert_eq!(logs[0].position.fees, 1.0);
+        assert_eq!(portfolio.open_positions["AAPL"].size, 200);
+        assert_eq!(portfolio.pending_orders.len(), 1);
+
+        portfolio.check_orders(&bar("AAPL", 2, 101.0, 102.0));
+        portfolio.check_orders(&bar("AAPL", 3, 102.0, 103.0));
+        assert!(portfolio.open_positions.is_empty());
+        assert!(portfolio.pending_orders.is_empty());
+        assert_eq!(portfolio.closed_positions.len(), 3);
+    }
+
+    #[test]
+    fn test_bracket_exit_cancels_its_sibling() {
+        let config = Config { slippage: SlippageModel::None, ..Config::default() };
+        let mut portfolio = Portfolio::new(config);
+        let signal = Signal::new_group("AAPL".into(), OrderGroup::Bracket {
+            entry: OrderType::MarketBuy(),
+            stop_loss: OrderType::StopMarketSell(OrderDistance::Fixed(95.0)),
+            take_profit: OrderType::LimitSell(OrderDistance::Fixed(105.0)),
+        });
+        portfolio.process_signal(&signal, 100.0, 0, None, &HashMap::new(), "test");
+
+        // The entry fill submits both exits
+        let logs = portfolio.check_orders(&bar("AAPL", 0, 100.0, 100.0));
+        assert_eq!(logs.len(), 1);
+        assert!(portfolio.open_positions.contains_key("AAPL"));
+        assert_eq!(portfolio.pending_orders.len(), 2);
+
+        let logs = portfolio.check_orders(&bar("AAPL", 1, 104.0, 106.0));
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].position.exit_price, Some(105.0));
+        assert!(portfolio.open_positions.is_empty());
+        assert!(portfolio.pending_orders.is_empty());
+        assert_eq!(portfolio.cancellations.len(), 1);
+        assert_eq!(portfolio.cancellations[0].reason, CancelReason::OcoSibling);
+    }
+
+    #[test]
+    fn test_partial_bracket_exit_shrinks_its_sibling() {
+        let config = Config {
+            slippage: SlippageModel::None,
+            execution: ExecutionConfig { max_participation: Some(0.01), ..ExecutionConfig::default() },
+            ..Config::default()
+        };
+        let mut portfolio = Portfolio::new(config);
+        let signal = Signal::new_group("AAPL".into(), OrderGroup::Bracket {
+            entry: OrderType::MarketBuy(),
+            stop_loss: OrderType::StopMarketSell(OrderDistance::Fixed(95.0)),
+            take_profit: OrderType::LimitSell(OrderDistance::Fixed(105.0)),
+        });
+        portfolio.process_signal(&signal, 100.0, 0, None, &HashMap::new(), "test");
+        assert_eq!(portfolio.check_orders(&bar("AAPL", 0, 100.0, 100.0)).len(), 1);
+
+        // 1% of 4000 shares: the target sells 40 of 100, the stop now covers the other 60
+        let logs = portfolio.check_orders(&Row { volume: 4000, ..bar("AAPL", 1, 104.0, 106.0) });
+        assert_eq!(logs[0].execution.size, 40);
+        assert_eq!(portfolio.open_positions["AAPL"].size, 60);
+        let sizes: Vec<i64> = portfolio.pending_orders.iter().map(|p| p.order.size).collect();
+        assert_eq!(sizes, vec![60, 100]);
+        assert!(portfolio.cancellations.is_empty());
+
+        // The stop closes the rest and cancels what is left of the target
+        let logs = portfolio.check_orders(&bar("AAPL", 2, 96.0, 94.0));
+        assert_eq!(logs[0].execution.size, 60);
+        assert!(portfolio.open_positions.is_empty());
+        assert!(portfolio.pending_orders.is_empty());
+        assert_eq!(portfolio.cancellations[0].reason, CancelReason::OcoSibling);
+    }
+
+    #[test]
+    fn test_cancel_and_replace_requests() {
+        let config = Config { slippage: SlippageModel::None, ..Config::default() };
+        let mut portfolio = Portfolio::new(config);
+        let limit = OrderType::LimitBuy(OrderDistance::Fixed(95.0));
+        let submit = |portfolio: &mut Portfolio, ticker: &str| {
+            let signal = Signal::new_trigger(ticker.into(), limit).with_good_until(OrderTimeline::GTC);
+            portfolio.process_signal(&signal, 100.0, 0, None, &HashMap::new(), "test")[0]
+        };
+        let (aapl, msft, tsla) = (submit(&mut portfolio, "AAPL"), submit(&mut portfolio, "MSFT"), submit(&mut portfolio, "TSLA"));
+        assert_eq!((aapl, msft, tsla), (OrderId(1), OrderId(2), OrderId(3)));
+
+        // The cancel comes on the bar that fills the order, which traded before the cancel
+        portfolio.process_signal(&Signal::new_cancel("AAPL".into(), aapl), 96.0, 1, None, &HashMap::new(), "test");
+        assert_eq!(portfolio.check_orders(&bar("AAPL", 1, 96.0, 97.0)).len(), 1);
+        let refused = &portfolio.refused_requests[0];
+        assert_eq!((refused.order_id, refused.request), (aapl, RequestKind::Cancel));
+        assert!(portfolio.rejections.is_empty());
+        // The journal keeps the order's filled state, with the refusal beside it
+        let late = portfolio.journal.of_kind(OrderEventKind::CancelRejected).next().unwrap();
+        assert_eq!((late.order_id, late.filled), (aapl, late.size));
+        assert_eq!(portfolio.journal.last(aapl).unwrap().kind, OrderEventKind::Filled);
+
+        // Replaced after its bar is checked, then fills at the new price
+        let replace = OrderReplace::new().with_order_type(OrderType::LimitBuy(OrderDistance::Fixed(98.0))).with_size(50);
+        portfolio.process_signal(&Signal::new_replace("MSFT".into(), msft, replace), 100.0, 1, None, &HashMap::new(), "test");
+        assert!(portfolio.check_orders(&bar("MSFT", 1, 100.0, 100.0)).is_empty());
+        let logs = portfolio.check_orders(&bar("MSFT", 2, 100.0, 99.0));
+        assert_eq!((logs[0].execution.price, logs[0].execution.size), (98.0, 50));
+
+        portfolio.process_signal(&Signal::new_cancel("TSLA".into(), tsla), 100.0, 1, None, &HashMap::new(), "test");
+        assert!(portfolio.check_orders(&bar("TSLA", 1, 100.0, 100.0)).is_empty());
+        assert_eq!(portfolio.cancellations[0].reason, CancelReason::Requested);
+        assert!(portfolio.pending_orders.is_empty());
+
+        portfolio.process_signal(&Signal::new_cancel("TSLA".into(), tsla), 100.0, 2, None, &HashMap::new(), "test");
+        assert_eq!(portfolio.refused_requests.len(), 2);
+        assert!(portfolio.rejections.is_empty());
+    }
+
+    #[test]
+    fn test_journal_records_order_lifecycle() {
+        let config = Config {
+            slippage: SlippageModel::None,
+            execution: ExecutionConfig { max_participation: Some(0.01), ..ExecutionConfig::default() },
+            ..Config::default()
+        };
+        let mut portfolio = Portfolio::new(config);
+        portfolio.open_positions.insert("AAPL".into(), Position::new("a".into(), "AAPL".into(), Side::Long, 150, 100.0, 0));
+        let sell = Signal::new_trigger("AAPL".into(), OrderType::MarketSell());
+        let id = portfolio.process_signal(&sell, 100.0, 0, None, &HashMap::new(), "test")[0];
+        // Already long, so a buy is refused
+        let buy = Signal::new_trigger("AAPL".into(), OrderType::MarketBuy());
+        assert!(portfolio.process_signal(&buy, 100.0, 0, None, &HashMap::new(), "test").is_empty());
+
+        portfolio.check_orders(&bar("AAPL", 1, 100.0, 101.0));
+        portfolio.check_orders(&bar("AAPL", 2, 101.0, 102.0));
+        let kinds: Vec<_> = portfolio.journal.order(id).map(|event| event.kind).collect();
+        assert_eq!(kinds, vec![
+            OrderEventKind::Submitted,
+            OrderEventKind::Accepted,
+            OrderEventKind::PartiallyFilled,
+            OrderEventKind::Filled,
+        ]);
+        let last = portfolio.journal.last(id).unwrap();
+        assert_eq!((last.filled, last.price), (150, Some(102.0)));
+        let rejected: Vec<_> = portfolio.journal.of_kind(OrderEventKind::Rejected).collect();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].reason, "AAPL already has a position on that side");
+    }
+}
@@ -1,9 +1,15 @@
-use crate::position::order::OrderType;
+use crate::position::order::{OrderGroup, OrderId, OrderReplace, OrderTimeline, OrderType};
 
 #[derive(Debug, Clone)]
 pub enum SignalType {
     /// A binary decision (e.g., "Buy", "Sell")
     Trigger(OrderType),
+    /// Linked orders, e.g. an entry with its stop-loss and take-profit
+    Group(OrderGroup),
+    /// Cancel a working order
+    Cancel(OrderId),
+    /// Change the prices, size or time in force of a working order
+    Replace(OrderId, OrderReplace),
     /// A continuous value (e.g., "Sentiment Score", "Momentum Strength")
     /// High values might imply stronger conviction or priority
     Value(f64),
@@ -14,6 +20,11 @@ pub enum SignalType {
 pub struct Signal {
     pub ticker: String,
     pub signal_type: SignalType,
+    /// Time in force of the order, EOD if not set
+    pub good_until: Option<OrderTimeline>,
+    /// Re-anchor relative stop and limit prices to each bar's close instead of
+    /// resting at the prices resolved at submission
+    pub repeg: bool,
 }
 
 impl Signal {
@@ -21,6 +32,35 @@ impl Signal {
         Self {
             ticker,
             signal_type: SignalType::Trigger(order_type),
+            good_until: None,
+            repeg: false,
+        }
+    }
+
+    pub fn new_group(ticker: String, group: OrderGroup) -> Self {
+        Self {
+            ticker,
+            signal_type: SignalType::Group(group),
+            good_until: None,
+            repeg: false,
+        }
+    }
+
+    pub fn new_cancel(ticker: String, id: OrderId) -> Self {
+        Self {
+            ticker,
+            signal_type: SignalType::Cancel(id),
+            good_until: None,
+            repeg: false,
+        }
+    }
+
+    pub fn new_replace(ticker: String, id: OrderId, replace: OrderReplace) -> Self {
+        Self {
+            ticker,
+            signal_type: SignalType::Replace(id, replace),
+            good_until: None,
+            repeg: false,
         }
     }
 
@@ -28,6 +68,18 @@ impl Signal {
         Self {
             ticker,
             signal_type: SignalType::Value(value),
+            good_until: None,
+            repeg: false,
         }
     }
+
+    pub fn with_good_until(mut self, good_until: OrderTimeline) -> Self {
+        self.good_until = Some(good_until);
+        self
+    }
+
+    pub fn with_repeg(mut self) -> Self {
+        self.repeg = true;
+        self
+    }
 }
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::indicators::trackers::{VarianceTracker, WindowTracker};
+use crate::indicators::window::Window;
+use crate::types::ohlcv::Row;
+
+/// How far a fill is pushed against the trade
+///
+/// Written in config files as e.g. `slippage = { Bps = 5.0 }` or `slippage = "None"`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum SlippageModel {
+    /// Fill exactly at the order's price
+    None,
+    /// Fixed basis points of the fill price (10.0 = 0.1%)
+    Bps(f64),
+    /// Fixed cents per share
+    Cents(f64),
+    /// Fraction of the fill bar's high - low range
+    RangeFraction(f64),
+    /// Multiple of the standard deviation of close-to-close returns over the last `bars` bars
+    Volatility { multiple: f64, bars: usize },
+}
+
+impl Default for SlippageModel {
+    fn default() -> Self {
+        SlippageModel::Bps(10.0)
+    }
+}
+
+impl SlippageModel {
+    /// Check the parameters are non-negative
+    pub fn validate(&self) -> Result<(), String> {
+        let value = match *self {
+            SlippageModel::None => return Ok(()),
+            SlippageModel::Bps(v) | SlippageModel::Cents(v) | SlippageModel::RangeFraction(v) => v,
+            SlippageModel::Volatility { multiple, bars } => {
+                if bars < 2 {
+                    return Err(format!("slippage Volatility needs bars >= 2, got {}", bars));
+                }
+                multiple
+            }
+        };
+        if value.is_finite() && value >= 0.0 {
+            Ok(())
+        } else {
+            Err(format!("slippage must be >= 0, got {:?}", self))
+        }
+    }
+}
+
+/// Applies a `SlippageModel` to fills, tracking the per-ticker volatility it may need
+#[derive(Debug, Clone)]
+pub struct Slippage {
+    pub model: SlippageModel,
+    /// Last close and recent close-to-close returns per ticker (Volatility model only)
+    returns: HashMap<String, (f64, VarianceTracker)>,
+}
+
+impl Slippage {
+    pub fn new(model: SlippageModel) -> Self {
+        Self {
+            model,
+            returns: HashMap::new(),
+        }
+    }
+
+    /// Feed a new bar once its fills are priced; only needed by the Volatility model
+    pub fn update(&mut self, row: &Row) {
+        let SlippageModel::Volatility { bars, .. } = self.model else {
+            return;
+        };
+        match self.returns.get_mut(&row.ticker) {
+            Some((last_close, tracker)) => {
+                if *last_close > 0.0 {
+                    tracker.push(row.timestamp, row.close / *last_close - 1.0);
+                }
+                *last_close = row.close;
+            }
+            None => {
+                let tracker = VarianceTracker::new(Window::Bars(bars));
+                self.returns.insert(row.ticker.clone(), (row.close, tracker));
+            }
+        }
+    }
+
+    /// Slippage per share for a fill at `price` on `row`, always >= 0
+    pub fn per_share(&self, price: f64, row: &Row) -> f64 {
+        match self.model {
+            SlippageModel::None => 0.0,
+            SlippageModel::Bps(bps) => price * bps / 10_000.0,
+            SlippageModel::Cents(cents) => cents / 100.0,
+            SlippageModel::RangeFraction(fraction) => (row.high - row.low) * fraction,
+            SlippageModel::Volatility { multiple, .. } => {
+                let sigma = self.returns.get(&row.ticker).and_then(|(_, tracker)| tracker.get()).unwrap_or(0.0);
+                price * sigma * multiple
+            }
+        }
+    }
+
+    /// Move `price` against the trade: buys pay more, sells receive less
+    ///
+    /// Returns the adjusted price and the slippage per share.
+    pub fn apply(&self, price: f64, is_buy: bool, row: &Row) -> (f64, f64) {
+        let slip = self.per_share(price, row);
+        if is_buy {
+            (price + slip, slip)
+        } else {
+            (price - slip, slip)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(close: f64) -> Row {
+        Row {
+            timestamp: 0,
+            open: close,
+            high: close + 1.0,
+            low: close - 1.0,
+            close,
+            volume: 1000,
+            ticker: "AAPL".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_slippage_is_adverse() {
+        let row = bar(100.0);
+        let bps = Slippage::new(SlippageModel::Bps(10.0));
+        assert_eq!(bps.apply(100.0, true, &row), (100.1, 0.1));
+        assert_eq!(bps.apply(100.0, false, &row), (99.9, 0.1));
+
+        let cents = Slippage::new(SlippageModel::Cents(2.0));
+        assert_eq!(cents.apply(100.0, false, &row), (99.98, 0.02));
+
+        let range = Slippage::new(SlippageModel::RangeFraction(0.25));
+        assert_eq!(range.apply(100.0, true, &row), (100.5, 0.5));
+    }
+
+    #[test]
+    fn test_volatility_slippage() {
+        let mut slippage = Slippage::new(SlippageModel::Volatility { multiple: 2.0, bars: 10 });
+        let row = bar(100.0);
+        slippage.update(&row);
+        assert_eq!(slippage.per_share(100.0, &row), 0.0);
+
+        for close in [101.0, 100.0, 101.0, 100.0] {
+            slippage.update(&bar(close));
+        }
+        let slip = slippage.per_share(100.0, &row);
+        assert!(slip > 1.0 && slip < 3.0, "{}", slip);
+    }
+}
@@ -1,17 +1,61 @@
+use std::path::{Path, PathBuf};
 use chrono::NaiveTime;
+use serde::Deserialize;
+use crate::backtest::borrow::{BorrowConfig, BorrowError};
+use crate::backtest::execution::ExecutionConfig;
+use crate::backtest::margin::MarginConfig;
+use crate::backtest::fees::{CommissionModel, RegulatoryFees};
+use crate::backtest::slippage::SlippageModel;
+use crate::backtest::impact::ImpactModel;
 use crate::indicators::window::Window;
 use crate::position::sizing::SizingStrategy;
 
-/// Global configuration for strategy testing
-#[derive(Debug, Clone)]
+/// Errors that can occur while loading or validating a Config
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("Invalid TOML config: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid JSON config: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Unsupported config file {0}, expected a .toml or .json extension")]
+    UnsupportedFormat(PathBuf),
+    #[error("Invalid config: {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Borrow(#[from] BorrowError),
+}
+
+/// Configuration for a backtest run
+///
+/// Each `BacktestEngine` owns its own copy, so engines with different
+/// settings can run side by side in one process.
+///
+/// Can be loaded from a TOML or JSON file with `Config::from_file`.
+/// Missing fields fall back to `Config::default()`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub starting_buying_power: f64,
     /// Market hours and trading sessions
     pub market_hours: MarketHours,
-    /// Maximum time in position (in trading minutes, hours, or days)
+    /// Maximum time in position (in trading minutes, hours, or days), not yet enforced
     pub max_position_time: Option<Window>,
-    /// slippage
-    pub slippage: f64,
+    /// Slippage applied to market and stop-market fills
+    pub slippage: SlippageModel,
+    /// Market impact of large orders, applied to market and stop-market fills
+    pub impact: ImpactModel,
+    /// Broker commission charged on every fill
+    pub commission: CommissionModel,
+    /// SEC fee and FINRA TAF charged on sells
+    pub regulatory_fees: RegulatoryFees,
+    /// Short borrow rates and locates
+    pub borrow: BorrowConfig,
+    /// Margin requirements, leverage limit and liquidation
+    pub margin: MarginConfig,
+    /// When and how orders fill
+    pub execution: ExecutionConfig,
     /// replace orders? How do you replace positions
     pub replacement_strategy: ReplacementStrategy,
     /// sizing strategy
@@ -19,7 +63,10 @@ pub struct Config {
 }
 
 /// Configuration for market hours and trading sessions
-#[derive(Debug, Clone)]
+///
+/// Times are written as `"HH:MM:SS"` strings in config files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct MarketHours {
     /// Include pre-market hours (typically 4:00 AM - 9:30 AM ET)
     pub include_premarket: bool,
@@ -41,7 +88,13 @@ impl Default for Config {
             market_hours: MarketHours::default(),
             max_position_time: Some(Window::Days(30)),
             starting_buying_power: 1e5,
-            slippage: 0.001, // 0.1% slippage
+            slippage: SlippageModel::Bps(10.0), // 0.1% slippage
+            impact: ImpactModel::None,
+            commission: CommissionModel::None,
+            regulatory_fees: RegulatoryFees::default(),
+            borrow: BorrowConfig::default(),
+            margin: MarginConfig::default(),
+            execution: ExecutionConfig::default(),
             replacement_strategy: ReplacementStrategy::Cancel,
             sizing_strategy: SizingStrategy::Fixed(100),
         }
@@ -61,6 +114,115 @@ impl Default for MarketHours {
     }
 }
 
+impl Config {
+    /// Load a config from a `.toml` or `.json` file and validate it
+    ///
+    /// Relative paths inside the file (e.g. `borrow.table`) are resolved against its directory.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let config: Config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents)?,
+            Some("json") => serde_json::from_str(&contents)?,
+            _ => return Err(ConfigError::UnsupportedFormat(path.to_path_buf())),
+        };
+        config.finish(path.parent())
+    }
+
+    /// Parse and validate a TOML config
+    pub fn from_toml_str(contents: &str) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(contents)?;
+        config.finish(None)
+    }
+
+    /// Parse and validate a JSON config
+    pub fn from_json_str(contents: &str) -> Result<Self, ConfigError> {
+        let config: Config = serde_json::from_str(contents)?;
+        config.finish(None)
+    }
+
+    /// Load the files the config points to, then validate it
+    fn finish(mut self, base_dir: Option<&Path>) -> Result<Self, ConfigError> {
+        self.borrow.load_table(base_dir)?;
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Check that the settings are consistent with each other
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: String| Err(ConfigError::Invalid(msg));
+
+        if !(self.starting_buying_power.is_finite() && self.starting_buying_power > 0.0) {
+            return invalid(format!("starting_buying_power must be > 0, got {}", self.starting_buying_power));
+        }
+        self.slippage.validate().map_err(ConfigError::Invalid)?;
+        self.impact.validate().map_err(ConfigError::Invalid)?;
+        self.commission.validate().map_err(ConfigError::Invalid)?;
+        self.regulatory_fees.validate().map_err(ConfigError::Invalid)?;
+        self.margin.validate().map_err(ConfigError::Invalid)?;
+        self.execution.validate().map_err(ConfigError::Invalid)?;
+        if !(self.borrow.default_rate.is_finite() && self.borrow.default_rate >= 0.0) {
+            return invalid(format!("borrow.default_rate must be >= 0, got {}", self.borrow.default_rate));
+        }
+
+        let hours = &self.market_hours;
+        if hours.market_open >= hours.market_close {
+            return invalid(format!(
+                "market_hours.market_open ({}) must be before market_close ({})",
+                hours.market_open, hours.market_close
+            ));
+        }
+        if hours.premarket_open > hours.market_open {
+            return invalid(format!(
+                "market_hours.premarket_open ({}) must not be after market_open ({})",
+                hours.premarket_open, hours.market_open
+            ));
+        }
+        if hours.postmarket_close < hours.market_close {
+            return invalid(format!(
+                "market_hours.postmarket_close ({}) must not be before market_close ({})",
+                hours.postmarket_close, hours.market_close
+            ));
+        }
+        self.execution.auction.validate(&self.market_hours).map_err(ConfigError::Invalid)?;
+
+        if let Some(window) = self.max_position_time {
+            let positive = match window {
+                Window::Bars(n) => n > 0,
+                Window::Minutes(n) | Window::Hours(n) | Window::HoursRounded(n)
+                | Window::Days(n) | Window::DaysRounded(n) => n > 0,
+            };
+            if !positive {
+                return invalid(format!("max_position_time must be positive, got {:?}", window));
+            }
+        }
+
+        match self.sizing_strategy {
+            SizingStrategy::Fixed(shares) if shares <= 0 => {
+                invalid(format!("sizing_strategy Fixed shares must be > 0, got {}", shares))
+            }
+            SizingStrategy::FixedDollar(amount) if amount <= 0.0 => {
+                invalid(format!("sizing_strategy FixedDollar amount must be > 0, got {}", amount))
+            }
+            SizingStrategy::PercentOfAccount(pct) if pct <= 0.0 || pct > 100.0 => {
+                invalid(format!("sizing_strategy PercentOfAccount must be in (0, 100], got {}", pct))
+            }
+            SizingStrategy::RiskBased { risk_percent, stop_distance }
+                if risk_percent <= 0.0 || stop_distance <= 0.0 || stop_distance >= 1.0 =>
+            {
+                invalid(format!(
+                    "sizing_strategy RiskBased needs risk_percent > 0 and 0 < stop_distance < 1, got {} and {}",
+                    risk_percent, stop_distance
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 impl MarketHours {
     /// Get the earliest valid time for the trading session on a given day
     pub fn earliest_valid_time(&self) -> NaiveTime {
@@ -80,6 +242,11 @@ impl MarketHours {
         }
     }
 
+    /// Check if a time of day is within regular trading hours
+    pub fn is_regular_time(&self, time: NaiveTime) -> bool {
+        time >= self.market_open && time <= self.market_close
+    }
+
     /// Check if a time of day is within valid trading hours
     pub fn is_valid_time(&self, time: NaiveTime) -> bool {
         let earliest = self.earliest_valid_time();
@@ -89,7 +256,7 @@ impl MarketHours {
 }
 
 /// When maximum buying power is reached, what do we do?
-#[derive(Debug,Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub enum ReplacementStrategy {
     Queue, // queue up positions that can't be filled because of bp constraints, check if they can be filled in on a FI basis once bp is freed
     ReplaceOldest, // automatically replace the oldest position that was filled
@@ -99,24 +266,83 @@ pub enum ReplacementStrategy {
 }
 
 
-// Global static config - in a real app you'd use lazy_static or once_cell
-// For now, we'll pass it around or use thread_local
-use std::sync::RwLock;
-use std::sync::OnceLock;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-static GLOBAL_CONFIG: OnceLock<RwLock<Config>> = OnceLock::new();
+    #[test]
+    fn test_from_toml_str() {
+        let config = Config::from_toml_str(r#"
+            starting_buying_power = 50000.0
+            slippage = { Cents = 1.0 }
+            max_position_time = { Minutes = 90 }
+            replacement_strategy = "Queue"
+            sizing_strategy = { PercentOfAccount = 10.0 }
+            commission = { PerShare = { rate = 0.005, min = 1.0 } }
 
-/// Initialize the global configuration
-pub fn init_config(config: Config) {
-    let _ = GLOBAL_CONFIG.set(RwLock::new(config));
-}
+            [margin]
+            initial = 0.5
+            max_leverage = 2.0
+            overrides = { GME = { initial = 1.0, maintenance = 1.0 } }
 
-/// Get a copy of the current global configuration
-pub fn get_config() -> Config {
-    GLOBAL_CONFIG
-        .get_or_init(|| RwLock::new(Config::default()))
-        .read()
-        .unwrap()
-        .clone()
-}
+            [market_hours]
+            include_premarket = true
+            market_open = "09:30:00"
+            market_close = "16:00:00"
+        "#).unwrap();
+
+        assert_eq!(config.starting_buying_power, 50000.0);
+        assert!(matches!(config.max_position_time, Some(Window::Minutes(90))));
+        assert!(matches!(config.replacement_strategy, ReplacementStrategy::Queue));
+        assert!(matches!(config.sizing_strategy, SizingStrategy::PercentOfAccount(p) if p == 10.0));
+        assert!(config.market_hours.include_premarket);
+        assert_eq!(config.commission, CommissionModel::PerShare { rate: 0.005, min: 1.0, max: None });
+        assert_eq!(config.margin.requirement("GME").maintenance, 1.0);
+        assert_eq!(config.margin.requirement("AAPL").maintenance, 0.25);
+        // Unset fields keep their defaults
+        assert_eq!(config.market_hours.postmarket_close, MarketHours::default().postmarket_close);
+    }
+
+    #[test]
+    fn test_from_json_str() {
+        let config = Config::from_json_str(r#"{
+            "slippage": "None",
+            "max_position_time": null,
+            "sizing_strategy": { "RiskBased": { "risk_percent": 1.0, "stop_distance": 0.02 } }
+        }"#).unwrap();
 
+        assert_eq!(config.slippage, SlippageModel::None);
+        assert!(config.max_position_time.is_none());
+        assert!(matches!(config.sizing_strategy, SizingStrategy::RiskBased { .. }));
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let err = Config::from_toml_str("slippage = { Bps = -1.0 }").unwrap_err();
+        assert!(err.to_string().contains("slippage"), "{}", err);
+
+        let err = Config::from_toml_str(r#"
+            [market_hours]
+            market_open = "16:00:00"
+            market_close = "09:30:00"
+        "#).unwrap_err();
+        assert!(err.to_string().contains("market_open"), "{}", err);
+
+        let err = Config::from_toml_str("[margin]\ninitial = 0.2").unwrap_err();
+        assert!(err.to_string().contains("maintenance margin"), "{}", err);
+
+        assert!(matches!(Config::from_toml_str("slipage = 0.1"), Err(ConfigError::Toml(_))));
+        assert!(matches!(Config::from_file("missing.toml"), Err(ConfigError::Io { .. })));
+
+        let path = std::env::temp_dir().join(format!("config_test_{}.yaml", std::process::id()));
+        std::fs::write(&path, "starting_buying_power: 1000\n").unwrap();
+        let result = Config::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ConfigError::UnsupportedFormat(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_example_config_is_valid() {
+        Config::from_toml_str(include_str!("../config.example.toml")).unwrap();
+    }
+}
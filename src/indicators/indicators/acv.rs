@@ -3,7 +3,7 @@ use crate::indicators::fields::CommonField;
 use crate::indicators::window::Window;
 use crate::indicators::indicator::Indicator;
 use crate::types::ohlcv::Row;
-use crate::config::get_config;
+use crate::config::MarketHours;
 use chrono::{DateTime, Utc, Timelike};
 
 /// Average Current Volume (ACV)
@@ -28,6 +28,9 @@ use chrono::{DateTime, Utc, Timelike};
 ///     }
 /// }
 /// 
+/// // Use the engine's session times instead of the defaults
+/// let acv = ACV::new(20).with_market_hours(context.market_hours.clone());
+///
 /// // Reset at end of day
 /// acv.on_market_close();
 /// ```
@@ -44,6 +47,9 @@ pub struct ACV {
     
     /// Optional premarket volume to include
     premarket_volume: f64,
+
+    /// Session times used to find the minutes since market open
+    market_hours: MarketHours,
 }
 
 impl ACV {
@@ -57,6 +63,7 @@ impl ACV {
             daily_avg_tracker: SumTracker::new(Window::Bars(days)),
             last_day_timestamp: None,
             premarket_volume: 0.0,
+            market_hours: MarketHours::default(),
         }
     }
     
@@ -67,8 +74,15 @@ impl ACV {
             daily_avg_tracker: SumTracker::new(Window::Bars(days)),
             last_day_timestamp: None,
             premarket_volume: premarket_vol,
+            market_hours: MarketHours::default(),
         }
     }
+
+    /// Use the given session times instead of `MarketHours::default()`
+    pub fn with_market_hours(mut self, market_hours: MarketHours) -> Self {
+        self.market_hours = market_hours;
+        self
+    }
     
     /// Set the premarket volume for the current day
     pub fn set_premarket_volume(&mut self, volume: f64) {
@@ -143,10 +157,9 @@ impl ACV {
     /// 
     /// # Returns
     /// Minutes since market open (can be negative for premarket)
-    fn timestamp_to_interval(timestamp: i64) -> i64 {
+    fn timestamp_to_interval(&self, timestamp: i64) -> i64 {
         let dt = DateTime::<Utc>::from_timestamp_nanos(timestamp);
-        let config = get_config();
-        let market_open = config.market_hours.market_open;
+        let market_open = self.market_hours.market_open;
         
         // Calculate minutes since market open
         let current_minutes = dt.hour() as i64 * 60 + dt.minute() as i64;
@@ -180,7 +193,7 @@ impl Indicator for ACV {
         let current_vol = self.current_volume();
         
         // Calculate interval (minutes since market open)
-        let interval = Self::timestamp_to_interval(timestamp);
+        let interval = self.timestamp_to_interval(timestamp);
         
         // Get expected volume distribution at this time
         let dist_val = Self::volume_distribution(interval);
@@ -225,7 +238,16 @@ mod tests {
     
     #[test]
     fn test_timestamp_to_interval() {
-        // This test requires proper config initialization
-        // and would need actual timestamps to test properly
+        let ts = chrono::NaiveDate::from_ymd_opt(2021, 3, 1).unwrap()
+            .and_hms_opt(10, 15, 0).unwrap()
+            .and_utc()
+            .timestamp_nanos_opt().unwrap();
+        assert_eq!(ACV::new(20).timestamp_to_interval(ts), 45);
+
+        let hours = MarketHours {
+            market_open: chrono::NaiveTime::from_hms_opt(10, 0, 0).unwrap(),
+            ..MarketHours::default()
+        };
+        assert_eq!(ACV::new(20).with_market_hours(hours).timestamp_to_interval(ts), 15);
     }
 }
\ No newline at end of file
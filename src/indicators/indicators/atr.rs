@@ -0,0 +1,46 @@
+use crate::indicators::trackers::{SumTracker, WindowTracker};
+use crate::indicators::window::Window;
+use crate::indicators::indicator::Indicator;
+use crate::types::ohlcv::Row;
+
+/// Average True Range (ATR)
+/// Simple average of the true range over the window; the first bar uses its high - low
+#[derive(Debug)]
+pub struct ATR {
+    tracker: SumTracker,
+    previous: Option<Row>,
+}
+
+impl ATR {
+    pub fn new(window: Window) -> Self {
+        Self {
+            tracker: SumTracker::new(window),
+            previous: None,
+        }
+    }
+}
+
+impl Indicator for ATR {
+    fn update(&mut self, row: &Row) {
+        let range = match &self.previous {
+            Some(previous) => row.true_range(previous),
+            None => row.high - row.low,
+        };
+        self.tracker.push(row.timestamp, range);
+        self.tracker.prune(row.timestamp);
+        self.previous = Some(row.clone());
+    }
+
+    fn get(&self) -> Option<f64> {
+        self.tracker.get()
+    }
+
+    fn reset(&mut self) {
+        self.tracker.clear();
+        self.previous = None;
+    }
+
+    fn name(&self) -> &str {
+        "ATR"
+    }
+}
@@ -2,6 +2,7 @@
 
 pub mod acv;
 pub mod adv;
+pub mod atr;
 pub mod highLow;
 pub mod movingAverage;
 pub mod momentum;
@@ -11,6 +12,7 @@ pub mod vwap;
 // Re-exports for convenience
 pub use acv::ACV;
 pub use adv::ADV;
+pub use atr::ATR;
 pub use highLow::{HighOfPeriod, LowOfPeriod};
 pub use movingAverage::MovingAverage;
 pub use rsi::RSI;
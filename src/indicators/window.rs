@@ -1,9 +1,9 @@
-use std::collections::VecDeque;
-use chrono::{DateTime, Duration, Utc, Datelike, Timelike};
-use crate::config::{get_config, MarketHours};
+use chrono::{DateTime, Duration, Utc, Timelike};
+use serde::Deserialize;
+use crate::config::MarketHours;
 
 /// Represents different time windows for indicators
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Deserialize)]
 pub enum Window {
     /// Number of minutes of data to look back
     Minutes(i64),
@@ -74,8 +74,9 @@ impl Window {
 
     /// Get the start time for this window from a given current time
     /// 
-    /// Handles rounding automatically for HoursRounded and DaysRounded variants
-    pub fn get_start_time(&self, current_time: DateTime<Utc>) -> DateTime<Utc> {
+    /// Handles rounding automatically for HoursRounded and DaysRounded variants;
+    /// DaysRounded rounds to the session open given by `market_hours`
+    pub fn get_start_time(&self, current_time: DateTime<Utc>, market_hours: &MarketHours) -> DateTime<Utc> {
         match self {
             Window::Bars(_) => current_time,
             Window::Minutes(m) => current_time - Duration::minutes(*m),
@@ -90,10 +91,7 @@ impl Window {
                     .unwrap_or(current_time);
                 rounded - Duration::hours(*h - 1)
             }
-            Window::DaysRounded(d) => {
-                let config = get_config();
-                Self::round_to_day_start(current_time, *d, &config.market_hours)
-            }
+            Window::DaysRounded(d) => Self::round_to_day_start(current_time, *d, market_hours),
         }
     }
 
@@ -7,4 +7,5 @@ pub mod indicators;
 pub mod types;
 pub mod backtest;
 pub mod strategy;
+pub mod strategies;
 pub mod utils;
@@ -1,19 +1,199 @@
-mod parsing;
-mod position;
-mod config;
-mod indicators;
-mod types;
-mod backtest;
-mod strategy;
-mod strategies;
-mod utils;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::PathBuf;
 
 use chrono::NaiveDate;
+use strategy_tester::backtest::engine::BacktestEngine;
+use strategy_tester::backtest::{equity, journal};
+use strategy_tester::backtest::metrics::PerformanceReport;
+use strategy_tester::config::Config;
+use strategy_tester::parsing::{self, loader::DataSource};
+use strategy_tester::position::strategy::Action;
+use strategy_tester::strategies;
+use strategy_tester::types::log;
+
+const USAGE: &str = "\
+Usage: strategy_tester <command> [options]
+
+Commands:
+  run              Backtest a strategy over a date range
+  inspect-data     Summarise the data files in a date range
+  list-strategies  List the strategies available to `run`
+
+Options:
+  --strategy <name>    Strategy to run (see list-strategies)
+  --start <date>       First day to load, YYYY-MM-DD
+  --end <date>         Last day to load, YYYY-MM-DD (defaults to --start)
+  --data-dir <dir>     Directory of daily parquet files
+  --pattern <fmt>      chrono format of the file names, e.g. nasdaq_data_%Y-%m-%d.parquet
+  --output <dir>       Directory for trade logs and the summary (run only)
+  --format <fmt>       Trade log format: csv (default), jsonl or parquet (run only)
+  --config <file>      TOML or JSON config file (run only, defaults to Config::default())";
+
+/// Parsed command line: a subcommand followed by `--key value` pairs
+struct Args {
+    command: String,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let command = args.next().ok_or("missing command")?;
+        let mut options = HashMap::new();
+        while let Some(key) = args.next() {
+            let name = key
+                .strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument: {}", key))?;
+            let value = args.next().ok_or_else(|| format!("missing value for --{}", name))?;
+            options.insert(name.to_string(), value);
+        }
+        Ok(Self { command, options })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn require(&self, name: &str) -> Result<&str, Box<dyn Error>> {
+        self.get(name).ok_or_else(|| format!("--{} is required", name).into())
+    }
+
+    fn date(&self, name: &str) -> Result<NaiveDate, Box<dyn Error>> {
+        let raw = self.require(name)?;
+        NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+            .map_err(|e| format!("invalid --{} {:?}: {}", name, raw, e).into())
+    }
+
+    /// The `--start`/`--end` range, with `--end` defaulting to `--start`
+    fn date_range(&self) -> Result<(NaiveDate, NaiveDate), Box<dyn Error>> {
+        let start = self.date("start")?;
+        let end = if self.get("end").is_some() { self.date("end")? } else { start };
+        Ok((start, end))
+    }
+
+    fn data_source(&self) -> DataSource {
+        let mut source = DataSource::default();
+        if let Some(dir) = self.get("data-dir") {
+            source.root = PathBuf::from(dir);
+        }
+        if let Some(pattern) = self.get("pattern") {
+            source.file_pattern = pattern.to_string();
+        }
+        source
+    }
+}
+
 fn main() {
-    let test_date = NaiveDate::from_ymd_opt(2021,3,1).unwrap();
-    let df_result = parsing::parquet::read_parquet_by_date(test_date);
-    match df_result {
-        Ok(df) => println!("{}", df.head(Some(5))),
-        Err(e) => eprintln!("Error: {}", e),
+    let result = Args::parse(std::env::args().skip(1)).and_then(|args| match args.command.as_str() {
+        "run" => run(&args),
+        "inspect-data" => inspect_data(&args),
+        "list-strategies" => {
+            list_strategies();
+            Ok(())
+        }
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(format!("unknown command: {}", other).into()),
+    });
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        eprintln!();
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+}
+
+fn list_strategies() {
+    for (name, _) in strategies::STRATEGIES {
+        println!("{}", name);
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let strategy_name = args.require("strategy")?;
+    let factory = strategies::find_strategy(strategy_name)
+        .ok_or_else(|| format!("unknown strategy: {} (see list-strategies)", strategy_name))?;
+    let (start, end) = args.date_range()?;
+    let source = args.data_source();
+    let output = PathBuf::from(args.get("output").unwrap_or(parsing::DATA_SAVE_DIR));
+    let format = args.get("format").unwrap_or("csv");
+    let write_trades = match format {
+        "csv" => log::write_csv,
+        "jsonl" => log::write_jsonl,
+        "parquet" => log::write_parquet,
+        other => return Err(format!("unknown --format {} (expected csv, jsonl or parquet)", other).into()),
+    };
+
+    let config = match args.get("config") {
+        Some(path) => Config::from_file(path)?,
+        None => Config::default(),
+    };
+    let mut engine = BacktestEngine::new(config, Box::new(factory));
+
+    let mut rows = source.rows(start, end)?;
+    let processed = engine.run(&mut rows)?;
+    let report = rows.into_report();
+
+    std::fs::create_dir_all(&output)?;
+    write_trades(&engine.trade_logs, &output.join(format!("trades.{}", format)))?;
+    let curve = &engine.portfolio.equity_curve;
+    equity::write_csv(curve.bars(), &output.join("equity.csv"))?;
+    equity::write_csv(&curve.daily(), &output.join("equity_daily.csv"))?;
+    journal::write_csv(engine.portfolio.journal.events(), &output.join("orders.csv"))?;
+
+    let entries = engine.trade_logs.iter().filter(|l| l.action == Action::Entry).count();
+    let exits = engine.trade_logs.len() - entries;
+    let realized_pnl: f64 = engine.portfolio.closed_positions.iter().filter_map(|p| p.net_pnl()).sum();
+
+    let mut summary = String::new();
+    writeln!(summary, "strategy: {}", strategy_name)?;
+    writeln!(summary, "range: {} to {}", start, end)?;
+    writeln!(summary, "data: {}", report)?;
+    writeln!(summary, "rows processed: {}", processed)?;
+    writeln!(summary, "trades: {} ({} entries, {} exits)", engine.trade_logs.len(), entries, exits)?;
+    writeln!(summary, "open positions: {}", engine.portfolio.open_positions.len())?;
+    writeln!(summary, "rejected orders: {}", engine.portfolio.rejections.len())?;
+    writeln!(summary, "refused cancel/replace requests: {}", engine.portfolio.refused_requests.len())?;
+    writeln!(summary, "expired or cancelled orders: {}", engine.portfolio.cancellations.len())?;
+    writeln!(summary, "order events: {} (see orders.csv)", engine.portfolio.journal.len())?;
+    writeln!(summary, "fills decided by intrabar path: {}", engine.portfolio.ambiguous_fills)?;
+    writeln!(summary, "realized pnl (net of fees): {:.2}", realized_pnl)?;
+    let slippage: f64 = engine.trade_logs.iter().map(|l| l.slippage).sum();
+    let impact: f64 = engine.trade_logs.iter().map(|l| l.impact).sum();
+    let fees: f64 = engine.trade_logs.iter().map(|l| l.fees.total()).sum();
+    writeln!(summary, "slippage: {:.2}, impact: {:.2}, fees: {:.2}", slippage, impact, fees)?;
+    writeln!(summary, "final cash: {:.2}, buying power: {:.2}", engine.portfolio.cash, engine.portfolio.buying_power())?;
+    if let Some(last) = curve.last() {
+        writeln!(summary, "final equity: {:.2} ({:.2} unrealized)", last.equity, last.unrealized_pnl)?;
+    }
+
+    writeln!(summary, "{}", PerformanceReport::compute(&engine.trade_logs, &curve.points(), 0.0))?;
+    std::fs::write(output.join("summary.txt"), &summary)?;
+
+    print!("{}", summary);
+    println!("output written to {}", output.display());
+    Ok(())
+}
+
+fn inspect_data(args: &Args) -> Result<(), Box<dyn Error>> {
+    let (start, end) = args.date_range()?;
+    let source = args.data_source();
+
+    let mut days = source.days(start, end)?;
+    let mut first = true;
+    for day in days.by_ref() {
+        let (date, df) = day?;
+        if first {
+            println!("{}", df.head(Some(5)));
+            first = false;
+        }
+        let tickers = df.column(parsing::frame::SYMBOL_COL)?.n_unique()?;
+        println!("{}: {} rows, {} tickers", date, df.height(), tickers);
     }
+    println!("{}", days.report());
+    Ok(())
 }
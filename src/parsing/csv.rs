@@ -0,0 +1,331 @@
+use std::path::{Path, PathBuf};
+use chrono::DateTime;
+use crate::types::ohlcv::Row;
+use super::frame::{SYMBOL_COL, TIME_COL, OPEN_COL, HIGH_COL, LOW_COL, CLOSE_COL, VOLUME_COL};
+
+/// Errors that can occur while reading CSV data
+#[derive(Debug, thiserror::Error)]
+pub enum CsvError {
+    #[error("Failed to read {path}: {source}")]
+    Read { path: PathBuf, source: ::csv::Error },
+    #[error("Failed to list {path}: {source}")]
+    Dir { path: PathBuf, source: std::io::Error },
+    #[error("Missing column {column} in {path}")]
+    MissingColumn { path: PathBuf, column: String },
+    #[error("Cannot derive a ticker from file name {0}")]
+    NoTicker(PathBuf),
+    #[error("Invalid value {value:?} for column {column} in {path} at line {line}")]
+    InvalidValue {
+        path: PathBuf,
+        line: u64,
+        column: String,
+        value: String,
+    },
+}
+
+/// How the time column is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// Integer seconds since the Unix epoch
+    EpochSeconds,
+    /// Integer milliseconds since the Unix epoch
+    EpochMillis,
+    /// Integer nanoseconds since the Unix epoch
+    EpochNanos,
+    /// ISO-8601 with a UTC offset, e.g. `2021-03-01T09:30:00-05:00`
+    ///
+    /// The offset is dropped and the wall-clock time kept, matching the Parquet data
+    /// where market hours are compared against the timestamp's time of day.
+    Iso8601,
+}
+
+impl TimestampFormat {
+    /// Parse a raw value into a nanosecond timestamp
+    pub fn parse(&self, value: &str) -> Option<i64> {
+        let value = value.trim();
+        match self {
+            TimestampFormat::EpochSeconds => value.parse::<i64>().ok()?.checked_mul(1_000_000_000),
+            TimestampFormat::EpochMillis => value.parse::<i64>().ok()?.checked_mul(1_000_000),
+            TimestampFormat::EpochNanos => value.parse::<i64>().ok(),
+            TimestampFormat::Iso8601 => {
+                let dt = DateTime::parse_from_rfc3339(value)
+                    .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f%:z"))
+                    .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"))
+                    .ok()?;
+                dt.naive_local().and_utc().timestamp_nanos_opt()
+            }
+        }
+    }
+}
+
+/// Header names of the OHLCV columns in a CSV file
+#[derive(Debug, Clone)]
+pub struct ColumnMap {
+    /// Ignored for the `PerTicker` layout, where the ticker comes from the file name
+    pub symbol: String,
+    pub time: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+}
+
+impl Default for ColumnMap {
+    fn default() -> Self {
+        Self {
+            symbol: SYMBOL_COL.to_string(),
+            time: TIME_COL.to_string(),
+            open: OPEN_COL.to_string(),
+            high: HIGH_COL.to_string(),
+            low: LOW_COL.to_string(),
+            close: CLOSE_COL.to_string(),
+            volume: VOLUME_COL.to_string(),
+        }
+    }
+}
+
+/// How tickers are spread across files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvLayout {
+    /// Each file holds many tickers, identified by the symbol column
+    PerFile,
+    /// Each file holds a single ticker named by the file stem, e.g. `AAPL.csv`
+    PerTicker,
+}
+
+/// Options describing a vendor's CSV export
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub columns: ColumnMap,
+    pub timestamp: TimestampFormat,
+    pub layout: CsvLayout,
+    pub delimiter: u8,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            columns: ColumnMap::default(),
+            timestamp: TimestampFormat::Iso8601,
+            layout: CsvLayout::PerFile,
+            delimiter: b',',
+        }
+    }
+}
+
+/// A set of CSV files read as one OHLCV data set
+#[derive(Debug, Clone)]
+pub struct CsvSource {
+    pub paths: Vec<PathBuf>,
+    pub options: CsvOptions,
+}
+
+impl CsvSource {
+    pub fn new(paths: Vec<PathBuf>, options: CsvOptions) -> Self {
+        Self { paths, options }
+    }
+
+    /// Use every `.csv` file in a directory, e.g. one file per ticker
+    pub fn from_dir(dir: impl AsRef<Path>, options: CsvOptions) -> Result<Self, CsvError> {
+        let dir = dir.as_ref();
+        let dir_err = |source| CsvError::Dir { path: dir.to_path_buf(), source };
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(dir_err)? {
+            let path = entry.map_err(dir_err)?.path();
+            if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(Self::new(paths, options))
+    }
+
+    /// Read all files into rows ordered by timestamp across all tickers (ties broken by ticker)
+    pub fn read_rows(&self) -> Result<Vec<Row>, CsvError> {
+        let mut rows = Vec::new();
+        for path in &self.paths {
+            self.read_file(path, &mut rows)?;
+        }
+        rows.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.ticker.cmp(&b.ticker)));
+        Ok(rows)
+    }
+
+    /// Same as `read_rows`, but as a fallible row stream like the Parquet path
+    pub fn rows(&self) -> Result<CsvRows, CsvError> {
+        Ok(CsvRows {
+            rows: self.read_rows()?.into_iter(),
+        })
+    }
+
+    fn read_file(&self, path: &Path, rows: &mut Vec<Row>) -> Result<(), CsvError> {
+        let read_err = |source| CsvError::Read { path: path.to_path_buf(), source };
+        let mut reader = ::csv::ReaderBuilder::new()
+            .delimiter(self.options.delimiter)
+            .trim(::csv::Trim::All)
+            .from_path(path)
+            .map_err(read_err)?;
+
+        let headers = reader.headers().map_err(read_err)?.clone();
+        let index_of = |column: &str| {
+            headers.iter().position(|h| h == column).ok_or_else(|| CsvError::MissingColumn {
+                path: path.to_path_buf(),
+                column: column.to_string(),
+            })
+        };
+
+        let cols = &self.options.columns;
+        let (symbol_idx, file_ticker) = match self.options.layout {
+            CsvLayout::PerFile => (Some(index_of(&cols.symbol)?), None),
+            CsvLayout::PerTicker => {
+                let ticker = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .ok_or_else(|| CsvError::NoTicker(path.to_path_buf()))?;
+                (None, Some(ticker.to_string()))
+            }
+        };
+        let time_idx = index_of(&cols.time)?;
+        let open_idx = index_of(&cols.open)?;
+        let high_idx = index_of(&cols.high)?;
+        let low_idx = index_of(&cols.low)?;
+        let close_idx = index_of(&cols.close)?;
+        let volume_idx = index_of(&cols.volume)?;
+
+        for record in reader.records() {
+            let record = record.map_err(read_err)?;
+            let line = record.position().map(|p| p.line()).unwrap_or(0);
+            let field = |idx: usize, column: &str| -> Result<&str, CsvError> {
+                record
+                    .get(idx)
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| CsvError::InvalidValue {
+                        path: path.to_path_buf(),
+                        line,
+                        column: column.to_string(),
+                        value: String::new(),
+                    })
+            };
+            let invalid = |column: &str, value: &str| CsvError::InvalidValue {
+                path: path.to_path_buf(),
+                line,
+                column: column.to_string(),
+                value: value.to_string(),
+            };
+            let price = |idx: usize, column: &str| -> Result<f64, CsvError> {
+                let value = field(idx, column)?;
+                value.parse::<f64>().map_err(|_| invalid(column, value))
+            };
+
+            let ticker = match (symbol_idx, &file_ticker) {
+                (Some(idx), _) => field(idx, &cols.symbol)?.to_string(),
+                (None, Some(ticker)) => ticker.clone(),
+                (None, None) => unreachable!("layout always provides a ticker source"),
+            };
+            let raw_time = field(time_idx, &cols.time)?;
+            let timestamp = self
+                .options
+                .timestamp
+                .parse(raw_time)
+                .ok_or_else(|| invalid(&cols.time, raw_time))?;
+            // Some vendors write volume as a float ("1200.0")
+            let raw_volume = field(volume_idx, &cols.volume)?;
+            let volume = raw_volume
+                .parse::<i64>()
+                .or_else(|_| raw_volume.parse::<f64>().map(|v| v as i64))
+                .map_err(|_| invalid(&cols.volume, raw_volume))?;
+
+            rows.push(Row {
+                timestamp,
+                open: price(open_idx, &cols.open)?,
+                high: price(high_idx, &cols.high)?,
+                low: price(low_idx, &cols.low)?,
+                close: price(close_idx, &cols.close)?,
+                volume,
+                ticker,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Row stream over a `CsvSource`, see `CsvSource::rows`
+pub struct CsvRows {
+    rows: std::vec::IntoIter<Row>,
+}
+
+impl Iterator for CsvRows {
+    type Item = Result<Row, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next().map(Ok)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("csv_test_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_timestamp_formats() {
+        let nanos = 1_614_591_000_000_000_000; // 2021-03-01 09:30:00 wall-clock
+        assert_eq!(TimestampFormat::EpochSeconds.parse("1614591000"), Some(nanos));
+        assert_eq!(TimestampFormat::EpochMillis.parse("1614591000000"), Some(nanos));
+        assert_eq!(TimestampFormat::EpochNanos.parse("1614591000000000000"), Some(nanos));
+        // Exchange wall-clock time is kept whatever the offset, as market hours expect
+        assert_eq!(TimestampFormat::Iso8601.parse("2021-03-01T09:30:00-05:00"), Some(nanos));
+        assert_eq!(TimestampFormat::Iso8601.parse("2021-03-01 09:30:00+00:00"), Some(nanos));
+        assert_eq!(TimestampFormat::Iso8601.parse("2021-03-01"), None);
+    }
+
+    #[test]
+    fn test_per_ticker_layout_with_column_mapping() {
+        let dir = temp_dir("per_ticker");
+        let header = "Date,O,H,L,C,V\n";
+        std::fs::write(dir.join("MSFT.csv"), format!("{header}120,1,2,0.5,1.5,100\n60,1,2,0.5,1.5,100\n")).unwrap();
+        std::fs::write(dir.join("AAPL.csv"), format!("{header}60,3,4,2.5,3.5,200.0\n")).unwrap();
+
+        let options = CsvOptions {
+            columns: ColumnMap {
+                time: "Date".into(),
+                open: "O".into(),
+                high: "H".into(),
+                low: "L".into(),
+                close: "C".into(),
+                volume: "V".into(),
+                ..ColumnMap::default()
+            },
+            timestamp: TimestampFormat::EpochSeconds,
+            layout: CsvLayout::PerTicker,
+            ..CsvOptions::default()
+        };
+        let rows = CsvSource::from_dir(&dir, options).unwrap().read_rows().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let order: Vec<(&str, i64)> = rows.iter().map(|r| (r.ticker.as_str(), r.timestamp / 1_000_000_000)).collect();
+        assert_eq!(order, vec![("AAPL", 60), ("MSFT", 60), ("MSFT", 120)]);
+        assert_eq!(rows[0].volume, 200);
+    }
+
+    #[test]
+    fn test_invalid_value_reports_line() {
+        let dir = temp_dir("invalid");
+        let path = dir.join("day.csv");
+        std::fs::write(&path, "symbol,time,open,high,low,close,volume\nAAPL,2021-03-01T09:30:00Z,1,2,0.5,abc,10\n").unwrap();
+
+        let err = CsvSource::new(vec![path], CsvOptions::default()).read_rows().unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(err, CsvError::InvalidValue { line: 2, ref column, .. } if column == "close"));
+    }
+}
@@ -0,0 +1,238 @@
+use polars::prelude::*;
+use crate::types::ohlcv::Row;
+
+/// Column names expected in a loaded OHLCV DataFrame
+pub const SYMBOL_COL: &str = "symbol";
+pub const TIME_COL: &str = "time";
+pub const OPEN_COL: &str = "open";
+pub const HIGH_COL: &str = "high";
+pub const LOW_COL: &str = "low";
+pub const CLOSE_COL: &str = "close";
+pub const VOLUME_COL: &str = "volume";
+
+/// Errors that can occur while converting a DataFrame into rows
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    #[error("Missing column: {0}")]
+    MissingColumn(String),
+    #[error("Column {column} has dtype {found}, expected {expected}")]
+    WrongDtype {
+        column: String,
+        expected: &'static str,
+        found: String,
+    },
+    #[error("Null value in column {column} at row {row}")]
+    NullValue { column: String, row: usize },
+    #[error(transparent)]
+    Polars(#[from] PolarsError),
+}
+
+/// Iterator that turns a loaded OHLCV DataFrame into `Row`s
+///
+/// Rows are yielded in timestamp order across all tickers (ties broken by symbol),
+/// which is the order `BacktestEngine::process_row` expects them in.
+///
+/// # Usage
+/// ```ignore
+/// let df = read_parquet_by_date(date)?;
+/// for row in FrameRows::new(&df)? {
+///     engine.process_row(&row?);
+/// }
+/// ```
+pub struct FrameRows {
+    symbol: StringChunked,
+    /// Timestamps already converted to nanoseconds
+    time: Int64Chunked,
+    open: Float64Chunked,
+    high: Float64Chunked,
+    low: Float64Chunked,
+    close: Float64Chunked,
+    volume: Int64Chunked,
+    idx: usize,
+    len: usize,
+}
+
+impl FrameRows {
+    /// Validate the DataFrame schema and sort it by time
+    ///
+    /// Accepted dtypes:
+    /// * `symbol` - String
+    /// * `time` - Datetime in any unit (converted to nanoseconds)
+    /// * `open`/`high`/`low`/`close` - Float32 or Float64
+    /// * `volume` - any integer or float type (cast to i64)
+    pub fn new(df: &DataFrame) -> Result<Self, FrameError> {
+        for name in [SYMBOL_COL, TIME_COL, OPEN_COL, HIGH_COL, LOW_COL, CLOSE_COL, VOLUME_COL] {
+            if df.column(name).is_err() {
+                return Err(FrameError::MissingColumn(name.to_string()));
+            }
+        }
+
+        let df = df.sort(
+            [TIME_COL, SYMBOL_COL],
+            SortMultipleOptions::default().with_maintain_order(true),
+        )?;
+
+        Ok(Self {
+            symbol: Self::symbol_column(&df)?,
+            time: Self::time_column(&df)?,
+            open: Self::price_column(&df, OPEN_COL)?,
+            high: Self::price_column(&df, HIGH_COL)?,
+            low: Self::price_column(&df, LOW_COL)?,
+            close: Self::price_column(&df, CLOSE_COL)?,
+            volume: Self::volume_column(&df)?,
+            idx: 0,
+            len: df.height(),
+        })
+    }
+
+    fn wrong_dtype(column: &str, expected: &'static str, found: &DataType) -> FrameError {
+        FrameError::WrongDtype {
+            column: column.to_string(),
+            expected,
+            found: found.to_string(),
+        }
+    }
+
+    fn symbol_column(df: &DataFrame) -> Result<StringChunked, FrameError> {
+        let col = df.column(SYMBOL_COL)?;
+        match col.dtype() {
+            DataType::String => Ok(col.str()?.rechunk().into_owned()),
+            other => Err(Self::wrong_dtype(SYMBOL_COL, "String", other)),
+        }
+    }
+
+    fn time_column(df: &DataFrame) -> Result<Int64Chunked, FrameError> {
+        let col = df.column(TIME_COL)?;
+        let nanos_per_unit = match col.dtype() {
+            DataType::Datetime(TimeUnit::Nanoseconds, _) => 1,
+            DataType::Datetime(TimeUnit::Microseconds, _) => 1_000,
+            DataType::Datetime(TimeUnit::Milliseconds, _) => 1_000_000,
+            other => return Err(Self::wrong_dtype(TIME_COL, "Datetime", other)),
+        };
+        // The physical representation of a Datetime is the epoch offset in its unit
+        let physical = col.cast(&DataType::Int64)?;
+        let time = physical.i64()?.rechunk().into_owned();
+        if nanos_per_unit == 1 {
+            Ok(time)
+        } else {
+            Ok(time * nanos_per_unit)
+        }
+    }
+
+    fn price_column(df: &DataFrame, name: &str) -> Result<Float64Chunked, FrameError> {
+        let col = df.column(name)?;
+        if !col.dtype().is_float() {
+            return Err(Self::wrong_dtype(name, "Float64", col.dtype()));
+        }
+        let col = col.cast(&DataType::Float64)?;
+        Ok(col.f64()?.rechunk().into_owned())
+    }
+
+    fn volume_column(df: &DataFrame) -> Result<Int64Chunked, FrameError> {
+        let col = df.column(VOLUME_COL)?;
+        if !col.dtype().is_primitive_numeric() {
+            return Err(Self::wrong_dtype(VOLUME_COL, "Int64", col.dtype()));
+        }
+        let col = col.cast(&DataType::Int64)?;
+        Ok(col.i64()?.rechunk().into_owned())
+    }
+
+    fn null(column: &str, row: usize) -> FrameError {
+        FrameError::NullValue {
+            column: column.to_string(),
+            row,
+        }
+    }
+
+    fn row_at(&self, i: usize) -> Result<Row, FrameError> {
+        Ok(Row {
+            ticker: self.symbol.get(i).ok_or_else(|| Self::null(SYMBOL_COL, i))?.to_string(),
+            timestamp: self.time.get(i).ok_or_else(|| Self::null(TIME_COL, i))?,
+            open: self.open.get(i).ok_or_else(|| Self::null(OPEN_COL, i))?,
+            high: self.high.get(i).ok_or_else(|| Self::null(HIGH_COL, i))?,
+            low: self.low.get(i).ok_or_else(|| Self::null(LOW_COL, i))?,
+            close: self.close.get(i).ok_or_else(|| Self::null(CLOSE_COL, i))?,
+            volume: self.volume.get(i).ok_or_else(|| Self::null(VOLUME_COL, i))?,
+        })
+    }
+}
+
+impl Iterator for FrameRows {
+    type Item = Result<Row, FrameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.len {
+            return None;
+        }
+        let row = self.row_at(self.idx);
+        self.idx += 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.idx;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_frame() -> DataFrame {
+        let mut df = df!(
+            "symbol" => ["MSFT", "AAPL", "AAPL", "MSFT"],
+            "time" => [300_000i64, 600_000, 300_000, 600_000],
+            "open" => [1.0, 2.0, 3.0, 4.0],
+            "high" => [1.5, 2.5, 3.5, 4.5],
+            "low" => [0.5, 1.5, 2.5, 3.5],
+            "close" => [1.2, 2.2, 3.2, 4.2],
+            "volume" => [10i64, 20, 30, 40]
+        ).unwrap();
+        let time = df.column("time").unwrap()
+            .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))
+            .unwrap();
+        df.with_column(time).unwrap();
+        df
+    }
+
+    #[test]
+    fn test_rows_sorted_by_time_then_symbol() {
+        let rows: Vec<Row> = FrameRows::new(&sample_frame())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let order: Vec<(&str, i64)> = rows.iter().map(|r| (r.ticker.as_str(), r.timestamp)).collect();
+        assert_eq!(order, vec![
+            ("AAPL", 300_000_000_000),
+            ("MSFT", 300_000_000_000),
+            ("AAPL", 600_000_000_000),
+            ("MSFT", 600_000_000_000),
+        ]);
+        assert_eq!(rows[0].open, 3.0);
+        assert_eq!(rows[0].volume, 30);
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let df = sample_frame().drop("volume").unwrap();
+        assert!(matches!(FrameRows::new(&df), Err(FrameError::MissingColumn(c)) if c == "volume"));
+    }
+
+    #[test]
+    fn test_wrong_dtype() {
+        let mut df = sample_frame();
+        df.with_column(Series::new("close".into(), ["a", "b", "c", "d"])).unwrap();
+        assert!(matches!(FrameRows::new(&df), Err(FrameError::WrongDtype { column, .. }) if column == "close"));
+    }
+
+    #[test]
+    fn test_null_value() {
+        let mut df = sample_frame();
+        df.with_column(Series::new("high".into(), [Some(1.0), None, Some(1.0), Some(1.0)])).unwrap();
+        let results: Vec<_> = FrameRows::new(&df).unwrap().collect();
+        assert_eq!(results.iter().filter(|r| r.is_err()).count(), 1);
+        assert!(results.iter().any(|r| matches!(r, Err(FrameError::NullValue { column, .. }) if column == "high")));
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::path::PathBuf;
+use chrono::{Datelike, NaiveDate, Weekday};
+use polars::prelude::*;
+use crate::types::ohlcv::Row;
+use super::frame::{FrameError, FrameRows};
+use super::parquet::read_parquet;
+
+/// Errors that can occur while loading a date range
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("Failed to read {path}: {source}")]
+    Read { path: PathBuf, source: PolarsError },
+    #[error("Invalid data in {path}: {source}")]
+    Frame { path: PathBuf, source: FrameError },
+    #[error("Start date {start} is after end date {end}")]
+    InvalidRange { start: NaiveDate, end: NaiveDate },
+}
+
+/// Where daily data files live and how they are named
+#[derive(Debug, Clone)]
+pub struct DataSource {
+    /// Directory containing the daily files
+    pub root: PathBuf,
+    /// chrono format string for a day's file name, e.g. `nasdaq_data_%Y-%m-%d.parquet`
+    pub file_pattern: String,
+}
+
+impl Default for DataSource {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from(super::DATA_LOAD_DIR),
+            file_pattern: format!("{}%Y-%m-%d.parquet", super::FILE_PREFIX),
+        }
+    }
+}
+
+impl DataSource {
+    pub fn new(root: impl Into<PathBuf>, file_pattern: impl Into<String>) -> Self {
+        Self {
+            root: root.into(),
+            file_pattern: file_pattern.into(),
+        }
+    }
+
+    /// Path of the file holding a given day's data
+    pub fn path_for(&self, date: NaiveDate) -> PathBuf {
+        self.root.join(date.format(&self.file_pattern).to_string())
+    }
+
+    /// Lazily load each day in `[start, end]` as a DataFrame, in chronological order
+    ///
+    /// Only one day is held in memory at a time.
+    pub fn days(&self, start: NaiveDate, end: NaiveDate) -> Result<DayStream<'_>, LoadError> {
+        if start > end {
+            return Err(LoadError::InvalidRange { start, end });
+        }
+        Ok(DayStream {
+            source: self,
+            next_date: Some(start),
+            end,
+            report: LoadReport::default(),
+        })
+    }
+
+    /// Lazily load every row in `[start, end]`, ordered by timestamp across days and tickers
+    pub fn rows(&self, start: NaiveDate, end: NaiveDate) -> Result<RowStream<'_>, LoadError> {
+        Ok(RowStream {
+            days: self.days(start, end)?,
+            current: None,
+        })
+    }
+}
+
+/// Summary of which days in a range were loaded or skipped
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+    pub loaded: Vec<NaiveDate>,
+    /// Saturdays and Sundays, skipped without touching the filesystem
+    pub weekends: Vec<NaiveDate>,
+    /// Weekdays without a data file (usually market holidays)
+    pub missing: Vec<NaiveDate>,
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "loaded {} days, skipped {} weekend days, {} missing",
+            self.loaded.len(),
+            self.weekends.len(),
+            self.missing.len()
+        )?;
+        if !self.missing.is_empty() {
+            let dates: Vec<String> = self.missing.iter().map(|d| d.to_string()).collect();
+            write!(f, ": {}", dates.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the days of a date range, see `DataSource::days`
+pub struct DayStream<'a> {
+    source: &'a DataSource,
+    next_date: Option<NaiveDate>,
+    end: NaiveDate,
+    report: LoadReport,
+}
+
+impl DayStream<'_> {
+    /// Days loaded and skipped so far
+    pub fn report(&self) -> &LoadReport {
+        &self.report
+    }
+
+    pub fn into_report(self) -> LoadReport {
+        self.report
+    }
+}
+
+impl Iterator for DayStream<'_> {
+    type Item = Result<(NaiveDate, DataFrame), LoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(date) = self.next_date {
+            if date > self.end {
+                self.next_date = None;
+                break;
+            }
+            self.next_date = date.succ_opt();
+
+            if matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+                self.report.weekends.push(date);
+                continue;
+            }
+
+            let path = self.source.path_for(date);
+            if !path.is_file() {
+                self.report.missing.push(date);
+                continue;
+            }
+
+            return Some(match read_parquet(&path) {
+                Ok(df) => {
+                    self.report.loaded.push(date);
+                    Ok((date, df))
+                }
+                Err(source) => Err(LoadError::Read { path, source }),
+            });
+        }
+        None
+    }
+}
+
+/// Iterator over the rows of a date range, see `DataSource::rows`
+pub struct RowStream<'a> {
+    days: DayStream<'a>,
+    current: Option<(NaiveDate, FrameRows)>,
+}
+
+impl RowStream<'_> {
+    /// Days loaded and skipped so far
+    pub fn report(&self) -> &LoadReport {
+        self.days.report()
+    }
+
+    pub fn into_report(self) -> LoadReport {
+        self.days.into_report()
+    }
+}
+
+impl Iterator for RowStream<'_> {
+    type Item = Result<Row, LoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((date, rows)) = self.current.as_mut() {
+                match rows.next() {
+                    Some(Ok(row)) => return Some(Ok(row)),
+                    Some(Err(source)) => {
+                        let path = self.days.source.path_for(*date);
+                        return Some(Err(LoadError::Frame { path, source }));
+                    }
+                    None => self.current = None,
+                }
+            }
+
+            let (date, df) = match self.days.next()? {
+                Ok(day) => day,
+                Err(e) => return Some(Err(e)),
+            };
+            match FrameRows::new(&df) {
+                Ok(rows) => self.current = Some((date, rows)),
+                Err(source) => {
+                    let path = self.days.source.path_for(date);
+                    return Some(Err(LoadError::Frame { path, source }));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_day(source: &DataSource, date: NaiveDate, ticker: &str) {
+        let ts = date.and_hms_opt(14, 30, 0).unwrap().and_utc().timestamp_millis();
+        let mut df = df!(
+            "symbol" => [ticker],
+            "time" => [ts],
+            "open" => [1.0],
+            "high" => [1.0],
+            "low" => [1.0],
+            "close" => [1.0],
+            "volume" => [100i64]
+        ).unwrap();
+        let time = df.column("time").unwrap()
+            .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))
+            .unwrap();
+        df.with_column(time).unwrap();
+        let file = std::fs::File::create(source.path_for(date)).unwrap();
+        ParquetWriter::new(file).finish(&mut df).unwrap();
+    }
+
+    #[test]
+    fn test_rows_skip_weekends_and_missing_days() {
+        let root = std::env::temp_dir().join(format!("loader_test_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let source = DataSource::new(&root, "day_%Y%m%d.parquet");
+
+        // Fri 2021-03-05, Mon 2021-03-08, Wed 2021-03-10; Tue 2021-03-09 missing
+        let d = |day| NaiveDate::from_ymd_opt(2021, 3, day).unwrap();
+        write_day(&source, d(5), "FRI");
+        write_day(&source, d(8), "MON");
+        write_day(&source, d(10), "WED");
+
+        let mut rows = source.rows(d(5), d(10)).unwrap();
+        let tickers: Vec<String> = rows.by_ref().map(|r| r.unwrap().ticker).collect();
+        let report = rows.into_report();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(tickers, vec!["FRI", "MON", "WED"]);
+        assert_eq!(report.loaded, vec![d(5), d(8), d(10)]);
+        assert_eq!(report.weekends, vec![d(6), d(7)]);
+        assert_eq!(report.missing, vec![d(9)]);
+    }
+}
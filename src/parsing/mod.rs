@@ -1,5 +1,9 @@
 const DATA_LOAD_DIR: &str = "/home/fred/Data/quant/5min/";
-const DATA_SAVE_DIR: &str = "../../data/";
+/// Default directory for backtest output
+pub const DATA_SAVE_DIR: &str = "../../data/";
 const FILE_PREFIX: &str = "nasdaq_data_";
 
-pub mod parquet;
\ No newline at end of file
+pub mod parquet;
+pub mod frame;
+pub mod loader;
+pub mod csv;
\ No newline at end of file
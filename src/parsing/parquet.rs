@@ -1,6 +1,14 @@
 use polars::prelude::*;
 use chrono::NaiveDate;
-use std::path::PathBuf;
+use std::path::Path;
+use super::loader::DataSource;
+
+/// Reads a single parquet file from an explicit path.
+pub fn read_parquet(path: &Path) -> PolarsResult<DataFrame> {
+    let file = std::fs::File::open(path)?;
+    ParquetReader::new(file).finish()
+}
+
 /// Reads a parquet file for a given date.
 /// 
 /// # Arguments
@@ -8,13 +16,10 @@ use std::path::PathBuf;
 /// 
 /// # Returns
 /// * `Result<DataFrame>` - The loaded DataFrame or an error
+///
 /// Box<dyn std::error::Error is a boxed pointer to any type that implements the Error trait
 /// The ? operator automatically converts std::io::Error and Polars eerrors -> Box<dyn Error>
 pub fn read_parquet_by_date(date: NaiveDate) -> std::result::Result<DataFrame, Box<dyn std::error::Error>> {
-    let file_path = PathBuf::from(super::DATA_LOAD_DIR)
-        .join(format!("{}{}.parquet", super::FILE_PREFIX, date.format("%Y-%m-%d")));
-    
-    let file = std::fs::File::open(&file_path)?;
-    let df = polars::prelude::ParquetReader::new(file).finish()?;
-    Ok(df)
+    let file_path = DataSource::default().path_for(date);
+    Ok(read_parquet(&file_path)?)
 }
\ No newline at end of file
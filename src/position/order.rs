@@ -1,5 +1,12 @@
+use chrono::DateTime;
+use serde::Serialize;
+use crate::backtest::execution::ExecutionConfig;
+use crate::config::MarketHours;
 use crate::types::ohlcv::Row;
-use crate::utils::get_mc_timestamp;
+use crate::utils::{get_mc_timestamp, get_mo_timestamp};
+
+const NANOS_PER_DAY: i64 = 24 * 3600 * 1_000_000_000;
+
 #[derive(Debug, Clone)]
 pub struct Order {
     pub order_type: OrderType, //contains order distance (price information)
@@ -7,9 +14,35 @@ pub struct Order {
     pub timestamp: i64, // submission timestamp
     pub good_until: OrderTimeline, // default will be EOD (end of day)
     pub size: i64,
+    /// Shares filled so far
     pub fill_size: i64,
+    /// Average price of the fills so far
     pub fill_price: Option<f64>,
+    /// Every fill of the order, more than one when volume limits split it across bars
+    pub executions: Vec<Execution>,
     pub completed: bool,
+    /// Whether a stop-limit's stop has been hit, turning it into a limit order
+    pub triggered: bool,
+    /// Whether the fill depended on the assumed intrabar path
+    pub ambiguous: bool,
+    /// Timestamp and close of the last regular-session bar a market-on-close order has seen
+    pub session_close: Option<(i64, f64)>,
+    /// Bars the order has been checked against
+    pub bars_checked: usize,
+    /// Why the order stopped working before filling in full, if it did
+    pub cancelled: Option<CancelReason>,
+    /// Most favourable price a trailing stop has seen: the high for sells, the low for buys
+    pub trail_extreme: Option<f64>,
+    /// Current stop of a trailing order, frozen once triggered
+    pub trigger_price: Option<f64>,
+    /// Stop price, resolved from the order's distance at submission
+    pub stop_price: Option<f64>,
+    /// Limit price, resolved from the order's distance at submission
+    pub limit_price: Option<f64>,
+    /// ATR at submission, for `OrderDistance::ATR` distances
+    pub atr: Option<f64>,
+    /// Re-anchor the stop and limit to each checked bar's close until triggered
+    pub repeg: bool,
 }
 
 impl Order {
@@ -17,7 +50,7 @@ impl Order {
         timestamp: i64, good_until: Option<OrderTimeline>, size: i64) -> Result<Self, OrderError> {
         // Validate the order type at a reasonable price point
         // This is a basic check; actual validation happens in check() with real prices
-        order_type.validate(100.0)?;
+        order_type.validate(100.0, None)?;
         
         Ok(Self {
             order_type,
@@ -27,92 +60,493 @@ impl Order {
             size,
             fill_size: 0,
             fill_price: None,
-            completed: false
+            executions: Vec::new(),
+            completed: false,
+            triggered: false,
+            ambiguous: false,
+            session_close: None,
+            bars_checked: 0,
+            cancelled: None,
+            trail_extreme: None,
+            trigger_price: None,
+            stop_price: None,
+            limit_price: None,
+            atr: None,
+            repeg: false,
         })
     }
 
-    pub fn check(&mut self, row: &Row) -> Result<(), OrderError> {
-        // Check if order is already completed or filled
+    /// Resolve the order's distances into stop and limit prices around `price`
+    ///
+    /// Called at submission with the signal bar's price and the ticker's ATR, so that
+    /// relative orders rest at a fixed price rather than following the market.
+    /// Trailing orders start trailing from `price`.
+    pub fn resolve_prices(&mut self, price: f64, atr: Option<f64>) -> Result<(), OrderError> {
+        self.order_type.validate(price, atr)?;
+        self.atr = atr;
+        let order_type = self.order_type;
+        let calculate = |distance: OrderDistance| distance.calculate(price, order_type, atr);
+        match order_type {
+            OrderType::LimitBuy(limit) | OrderType::LimitSell(limit) => {
+                self.limit_price = Some(calculate(limit)?);
+            }
+            OrderType::StopMarketBuy(stop) | OrderType::StopMarketSell(stop) => {
+                self.stop_price = Some(calculate(stop)?);
+            }
+            OrderType::StopLimitBuy(stop, limit) | OrderType::StopLimitSell(stop, limit) => {
+                self.stop_price = Some(calculate(stop)?);
+                self.limit_price = Some(calculate(limit)?);
+            }
+            OrderType::TrailingStopMarketBuy(trail) | OrderType::TrailingStopMarketSell(trail)
+            | OrderType::TrailingStopLimitBuy(trail, _) | OrderType::TrailingStopLimitSell(trail, _) => {
+                self.trigger_price = Some(calculate(trail)?);
+                self.trail_extreme = Some(price);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Stop and limit prices, resolved around the open of the first checked bar
+    /// if the order wasn't resolved at submission
+    fn prices(&mut self, row: &Row) -> Result<(Option<f64>, Option<f64>), OrderError> {
+        let resolved = match self.order_type {
+            OrderType::LimitBuy(_) | OrderType::LimitSell(_) => self.limit_price.is_some(),
+            OrderType::StopMarketBuy(_) | OrderType::StopMarketSell(_) => self.stop_price.is_some(),
+            OrderType::StopLimitBuy(_, _) | OrderType::StopLimitSell(_, _) => {
+                self.stop_price.is_some() && self.limit_price.is_some()
+            }
+            _ => true,
+        };
+        if !resolved {
+            self.resolve_prices(row.open, self.atr)?;
+        }
+        Ok((self.stop_price, self.limit_price))
+    }
+
+    /// Check the order against a new bar, filling or expiring it
+    ///
+    /// `market_hours` decides when an EOD order expires, `execution` the fill price and
+    /// how many shares the bar's volume allows. Returns the execution on this bar, if any;
+    /// the order stays open until all of `size` has filled.
+    pub fn check(&mut self, row: &Row, market_hours: &MarketHours, execution: &ExecutionConfig) -> Result<Option<Execution>, OrderError> {
+        // Check if order is already completed
         if self.completed {
             return Err(OrderError::AlreadyCompleted);
         }
-        if self.fill_price.is_some() {
-            return Err(OrderError::AlreadyFilled);
-        }
         
-        // Check if order has expired
+        // Check if order has expired; any unfilled remainder is dropped.
+        // Auction orders live until their auction.
         let expired = match self.good_until {
-            OrderTimeline::GTC => false,
+            _ if self.order_type.is_auction() => false,
+            OrderTimeline::GTC | OrderTimeline::IOC | OrderTimeline::FOK => false,
             OrderTimeline::EOD => {
-                row.timestamp > get_mc_timestamp(self.timestamp)
+                row.timestamp > get_mc_timestamp(self.timestamp, market_hours)
             }
+            OrderTimeline::GTD(until) => row.timestamp > until,
+            OrderTimeline::Bars(bars) => self.bars_checked >= bars,
         };
         
         if expired {
-            self.completed = true;
-            return Ok(());
+            self.cancel(CancelReason::Expired);
+            return Ok(None);
         }
+        self.bars_checked += 1;
+        self.ambiguous = false;
+        let immediate = !self.order_type.is_auction()
+            && matches!(self.good_until, OrderTimeline::IOC | OrderTimeline::FOK);
         
         // Check price conditions for filling the order
-        match self.order_type {
+        let mut fill_timestamp = row.timestamp;
+        let regular_session = market_hours.is_regular_time(DateTime::from_timestamp_nanos(row.timestamp).time());
+        let (stop, limit) = self.prices(row)?;
+        let (stop, limit) = (stop.unwrap_or_default(), limit.unwrap_or_default());
+        let price = match self.order_type {
             OrderType::MarketBuy() | OrderType::MarketSell() => {
-                // Market orders fill immediately at current price
-                self.fill_price = Some(row.close);
+                // Market orders fill immediately at the timing's reference price
+                Some(execution.timing.market_price(row))
             }
-            OrderType::LimitBuy(distance) => {
-                let price = distance.calculate(row.close, self.order_type, None)?;
-                if row.low <= price {
-                    self.fill_price = Some(row.low);
-                }
+            OrderType::LimitBuy(_) => Self::buy_limit_fill(limit, row, execution),
+            OrderType::LimitSell(_) => Self::sell_limit_fill(limit, row, execution),
+            // A stop that filled partially is a market order for the rest
+            OrderType::StopMarketBuy(_) | OrderType::StopMarketSell(_) if self.triggered => {
+                Some(execution.timing.market_price(row))
             }
-            OrderType::LimitSell(distance) => {
-                let price = distance.calculate(row.close, self.order_type, None)?;
-                if row.high >= price {
-                    self.fill_price = Some(row.high);
+            OrderType::StopMarketBuy(_) => Self::buy_stop_trigger(stop, row),
+            OrderType::StopMarketSell(_) => Self::sell_stop_trigger(stop, row),
+            OrderType::StopLimitBuy(_, _) => {
+                if self.triggered {
+                    Self::buy_limit_fill(limit, row, execution)
+                } else if let Some(trigger) = Self::buy_stop_trigger(stop, row) {
+                    self.buy_limit_after_trigger(trigger, limit, row, execution)
+                } else {
+                    None
                 }
             }
-            OrderType::StopMarketBuy(distance) => {
-                let price = distance.calculate(row.close, self.order_type, None)?;
-                if row.high >= price {
-                    self.fill_price = Some(row.high);
+            OrderType::StopLimitSell(_, _) => {
+                if self.triggered {
+                    Self::sell_limit_fill(limit, row, execution)
+                } else if let Some(trigger) = Self::sell_stop_trigger(stop, row) {
+                    self.sell_limit_after_trigger(trigger, limit, row, execution)
+                } else {
+                    None
                 }
             }
-            OrderType::StopMarketSell(distance) => {
-                let price = distance.calculate(row.close, self.order_type, None)?;
-                if row.low <= price {
-                    self.fill_price = Some(row.low);
+            OrderType::TrailingStopMarketBuy(_) | OrderType::TrailingStopMarketSell(_) if self.triggered => {
+                Some(execution.timing.market_price(row))
+            }
+            OrderType::TrailingStopMarketBuy(trail) | OrderType::TrailingStopMarketSell(trail) => {
+                self.trailing_trigger(trail, row, execution)?
+            }
+            OrderType::TrailingStopLimitBuy(trail, limit_offset) => {
+                if self.triggered {
+                    let limit = self.trailing_limit(limit_offset)?;
+                    Self::buy_limit_fill(limit, row, execution)
+                } else if let Some(trigger) = self.trailing_trigger(trail, row, execution)? {
+                    let limit = self.trailing_limit(limit_offset)?;
+                    self.buy_limit_after_trigger(trigger, limit, row, execution)
+                } else {
+                    None
                 }
             }
-            OrderType::StopLimitBuy(stop_distance, limit_distance) => {
-                let stop_price = stop_distance.calculate(row.close, self.order_type, None)?;
-                let limit_price = limit_distance.calculate(row.close, self.order_type, None)?;
-                // Order triggers when price rises to stop_price, fills at limit_price or better
-                if row.high >= stop_price && row.low <= limit_price {
-                    self.fill_price = Some(row.low.max(limit_price));
+            OrderType::TrailingStopLimitSell(trail, limit_offset) => {
+                if self.triggered {
+                    let limit = self.trailing_limit(limit_offset)?;
+                    Self::sell_limit_fill(limit, row, execution)
+                } else if let Some(trigger) = self.trailing_trigger(trail, row, execution)? {
+                    let limit = self.trailing_limit(limit_offset)?;
+                    self.sell_limit_after_trigger(trigger, limit, row, execution)
+                } else {
+                    None
                 }
             }
-            OrderType::StopLimitSell(stop_distance, limit_distance) => {
-                let stop_price = stop_distance.calculate(row.close, self.order_type, None)?;
-                let limit_price = limit_distance.calculate(row.close, self.order_type, None)?;
-                // Order triggers when price drops to stop_price, fills at limit_price or better
-                if row.low <= stop_price && row.high >= limit_price {
-                    self.fill_price = Some(row.high.min(limit_price));
+            OrderType::AuctionOpen(_) => {
+                // The opening auction prints the open of the day's first regular-session bar;
+                // orders submitted after the open wait for the next day's
+                let open = get_mo_timestamp(self.timestamp, market_hours);
+                let auction = if self.timestamp < open { open } else { open + NANOS_PER_DAY };
+                (row.timestamp >= auction && regular_session).then_some(row.open)
+            }
+            OrderType::AuctionClose(_) => {
+                // The closing auction prints the close of the day's last regular-session bar,
+                // which is only known to be the last once a later bar arrives
+                let close = get_mc_timestamp(self.timestamp, market_hours);
+                if row.timestamp < close {
+                    if regular_session {
+                        self.session_close = Some((row.timestamp, row.close));
+                    }
+                    None
+                } else if row.timestamp == close {
+                    Some(row.close)
+                } else if let Some((timestamp, close)) = self.session_close {
+                    fill_timestamp = timestamp;
+                    Some(close)
+                } else {
+                    // No regular-session bar after submission, the auction was missed
+                    self.cancel(CancelReason::AuctionMissed);
+                    None
                 }
             }
-            OrderType::AuctionOpen() | OrderType::AuctionClose() => {
-                // Auction orders not yet implemented
-                return Ok(());
+        };
+        let Some(price) = price else {
+            if immediate {
+                self.cancel(CancelReason::NotFilled);
+            } else if self.repeg && !self.triggered && !self.is_trailing() {
+                self.resolve_prices(row.close, self.atr)?;
             }
+            return Ok(None);
+        };
+        if matches!(self.order_type,
+            OrderType::StopMarketBuy(_) | OrderType::StopMarketSell(_)
+            | OrderType::TrailingStopMarketBuy(_) | OrderType::TrailingStopMarketSell(_)
+        ) {
+            self.triggered = true;
         }
-        
-        // Mark order as filled if fill price was set
-        if self.fill_price.is_some() {
-            self.fill_size = self.size; // assume sufficient liquidity
-            self.completed = true;
+
+        // Fill as much as the bar's volume allows; auctions fill in full
+        let remaining = self.size - self.fill_size;
+        let size = match execution.fill_capacity(row) {
+            Some(capacity) if !self.order_type.is_auction() => capacity.min(remaining),
+            _ => remaining,
+        };
+        if immediate && (size <= 0 || (self.good_until == OrderTimeline::FOK && size < remaining)) {
+            self.cancel(CancelReason::NotFilled);
+            return Ok(None);
         }
-        
+        if size <= 0 {
+            return Ok(None);
+        }
+        let fill = Execution { timestamp: fill_timestamp, price, size };
+        self.record_fill(fill);
+        if immediate && !self.completed {
+            // IOC keeps what filled and cancels the rest
+            self.cancel(CancelReason::NotFilled);
+        }
+        Ok(Some(fill))
+    }
+
+    /// Apply a replace request: new prices (resolved around `price`), size or time in force
+    ///
+    /// Fills so far are kept. The order is left unchanged if the replacement would flip
+    /// its side or leave it no larger than what has already filled. A new time in force
+    /// counts from `timestamp`.
+    pub fn replace(&mut self, replace: &OrderReplace, price: f64, atr: Option<f64>, timestamp: i64) -> Result<(), OrderError> {
+        let mut order = self.clone();
+        if let Some(order_type) = replace.order_type {
+            if order_type.is_buy() != self.order_type.is_buy() || order_type.is_auction() != self.order_type.is_auction() {
+                return Err(OrderError::SideChanged);
+            }
+            order.order_type = order_type;
+            order.stop_price = None;
+            order.limit_price = None;
+            order.trail_extreme = None;
+            order.trigger_price = None;
+            order.triggered = false;
+            order.resolve_prices(price, atr)?;
+        }
+        if let Some(size) = replace.size {
+            if size <= self.fill_size {
+                return Err(OrderError::SizeBelowFilled);
+            }
+            order.size = size;
+        }
+        if let Some(good_until) = replace.good_until {
+            order.good_until = good_until;
+            order.timestamp = timestamp;
+            order.bars_checked = 0;
+        }
+        *self = order;
         Ok(())
     }
+
+    /// Fill a market-on-close order still waiting for the bar after the session's last one
+    /// when the data ends
+    ///
+    /// The last regular-session bar seen is taken as the session's last; without one, the
+    /// auction was missed and the order is cancelled. Other orders are left as they are.
+    pub fn settle_close_auction(&mut self) -> Option<Execution> {
+        if self.completed || !matches!(self.order_type, OrderType::AuctionClose(_)) {
+            return None;
+        }
+        let Some((timestamp, price)) = self.session_close else {
+            self.cancel(CancelReason::AuctionMissed);
+            return None;
+        };
+        let fill = Execution { timestamp, price, size: self.size - self.fill_size };
+        self.record_fill(fill);
+        Some(fill)
+    }
+
+    /// Add a fill to the executions and average fill price
+    fn record_fill(&mut self, fill: Execution) {
+        self.fill_price = Some(
+            (self.fill_price.unwrap_or(0.0) * self.fill_size as f64 + fill.price * fill.size as f64)
+                / (self.fill_size + fill.size) as f64,
+        );
+        self.fill_size += fill.size;
+        self.executions.push(fill);
+        self.completed = self.fill_size >= self.size;
+    }
+
+    /// Stop working the order, keeping any fills it already had
+    pub fn cancel(&mut self, reason: CancelReason) {
+        self.completed = true;
+        self.cancelled = Some(reason);
+    }
+
+    /// Take `shares` off the order's size, as when an OCO sibling filled that many
+    ///
+    /// The size never drops below what has filled; an order with nothing left to fill
+    /// is cancelled with `reason`.
+    pub fn reduce(&mut self, shares: i64, reason: CancelReason) {
+        self.size = (self.size - shares).max(self.fill_size);
+        if self.fill_size >= self.size {
+            self.cancel(reason);
+        }
+    }
+
+    /// Whether the order's next fill is at the timing's market price: a market order,
+    /// or a stop-market that triggered on an earlier bar
+    pub fn fills_at_market(&self) -> bool {
+        match self.order_type {
+            OrderType::MarketBuy() | OrderType::MarketSell() => true,
+            order_type => order_type.is_marketable() && self.triggered,
+        }
+    }
+
+    /// Whether the order is a trailing stop, which moves its stop by itself
+    fn is_trailing(&self) -> bool {
+        matches!(self.order_type,
+            OrderType::TrailingStopMarketBuy(_) | OrderType::TrailingStopMarketSell(_)
+            | OrderType::TrailingStopLimitBuy(_, _) | OrderType::TrailingStopLimitSell(_, _)
+        )
+    }
+
+    /// Whether the order opens or closes a long position
+    pub fn is_long(&self) -> bool {
+        match self.open_or_close {
+            OrderAction::Open => self.order_type.is_buy(),
+            OrderAction::Close => self.order_type.is_sell(),
+        }
+    }
+
+    /// Ratchet a trailing stop with `row` and return the price it triggers at, if it does
+    ///
+    /// The stop trails the most favourable price since the order's first bar by `trail`.
+    /// The bar's open ratchets it first; the bar's favourable extreme only counts before the
+    /// adverse one when the intrabar path says it came first.
+    fn trailing_trigger(&mut self, trail: OrderDistance, row: &Row, execution: &ExecutionConfig) -> Result<Option<f64>, OrderError> {
+        let is_buy = self.order_type.is_buy();
+        // Buys trail the low and trigger above it, sells trail the high and trigger below it
+        let favourable = |a: f64, b: f64| if is_buy { a.min(b) } else { a.max(b) };
+        let (best, worst) = if is_buy { (row.low, row.high) } else { (row.high, row.low) };
+        let triggers = |stop: f64, price: f64| if is_buy { price >= stop } else { price <= stop };
+
+        let mut extreme = favourable(self.trail_extreme.unwrap_or(row.open), row.open);
+        let mut stop = trail.calculate(extreme, self.order_type, self.atr)?;
+        self.trail_extreme = Some(extreme);
+        self.trigger_price = Some(stop);
+        if triggers(stop, row.open) {
+            self.triggered = true;
+            return Ok(Some(row.open));
+        }
+
+        let best_first = execution.intrabar_path.high_first(row, self.is_long()) != is_buy;
+        if !best_first && triggers(stop, worst) {
+            self.triggered = true;
+            return Ok(Some(stop));
+        }
+        extreme = favourable(extreme, best);
+        let previous = stop;
+        stop = trail.calculate(extreme, self.order_type, self.atr)?;
+        self.trail_extreme = Some(extreme);
+        self.trigger_price = Some(stop);
+        // After the favourable extreme, the bar still reaches the adverse one (best first)
+        // or only the close
+        let after = if best_first { worst } else { row.close };
+        if triggers(stop, after) {
+            self.triggered = true;
+            // Only hit because the stop moved up (or down) within the bar first
+            self.ambiguous = !triggers(previous, after);
+            return Ok(Some(stop));
+        }
+        Ok(None)
+    }
+
+    /// Limit of a triggered trailing stop-limit, `limit_offset` away from its trigger
+    fn trailing_limit(&self, limit_offset: OrderDistance) -> Result<f64, OrderError> {
+        let trigger = self.trigger_price.ok_or(OrderError::InvalidOrder)?;
+        limit_offset.calculate(trigger, self.order_type, self.atr)
+    }
+
+    /// A triggered buy stop-limit becomes a buy limit at the trigger; it fills there if the
+    /// limit allows, otherwise only if price comes back down to the limit afterwards
+    fn buy_limit_after_trigger(&mut self, trigger: f64, limit: f64, row: &Row, execution: &ExecutionConfig) -> Option<f64> {
+        self.triggered = true;
+        if trigger <= limit {
+            Some(trigger)
+        } else if trigger == row.open {
+            Self::trades_below(row.low, limit, execution).then_some(limit)
+        } else {
+            self.ambiguous = Self::trades_below(row.low, limit, execution)
+                && !Self::trades_below(row.close, limit, execution);
+            let after_trigger = if execution.intrabar_path.high_first(row, self.is_long()) {
+                row.low
+            } else {
+                row.close
+            };
+            Self::trades_below(after_trigger, limit, execution).then_some(limit)
+        }
+    }
+
+    /// Sell side of `buy_limit_after_trigger`
+    fn sell_limit_after_trigger(&mut self, trigger: f64, limit: f64, row: &Row, execution: &ExecutionConfig) -> Option<f64> {
+        self.triggered = true;
+        if trigger >= limit {
+            Some(trigger)
+        } else if trigger == row.open {
+            Self::trades_above(row.high, limit, execution).then_some(limit)
+        } else {
+            self.ambiguous = Self::trades_above(row.high, limit, execution)
+                && !Self::trades_above(row.close, limit, execution);
+            let after_trigger = if execution.intrabar_path.high_first(row, self.is_long()) {
+                row.close
+            } else {
+                row.high
+            };
+            Self::trades_above(after_trigger, limit, execution).then_some(limit)
+        }
+    }
+
+    /// Whether `price` trades at or below `level`, or a full tick below it with `trade_through`
+    fn trades_below(price: f64, level: f64, execution: &ExecutionConfig) -> bool {
+        if execution.trade_through {
+            price <= level - execution.tick_size
+        } else {
+            price <= level
+        }
+    }
+
+    /// Whether `price` trades at or above `level`, or a full tick above it with `trade_through`
+    fn trades_above(price: f64, level: f64, execution: &ExecutionConfig) -> bool {
+        if execution.trade_through {
+            price >= level + execution.tick_size
+        } else {
+            price >= level
+        }
+    }
+
+    /// A buy limit fills at the open if the bar gaps below it, else at the limit once touched
+    fn buy_limit_fill(limit: f64, row: &Row, execution: &ExecutionConfig) -> Option<f64> {
+        if Self::trades_below(row.open, limit, execution) {
+            Some(row.open)
+        } else if Self::trades_below(row.low, limit, execution) {
+            Some(limit)
+        } else {
+            None
+        }
+    }
+
+    /// A sell limit fills at the open if the bar gaps above it, else at the limit once touched
+    fn sell_limit_fill(limit: f64, row: &Row, execution: &ExecutionConfig) -> Option<f64> {
+        if Self::trades_above(row.open, limit, execution) {
+            Some(row.open)
+        } else if Self::trades_above(row.high, limit, execution) {
+            Some(limit)
+        } else {
+            None
+        }
+    }
+
+    /// Price a buy stop triggers at: the open if the bar gaps above the stop, else the stop
+    fn buy_stop_trigger(stop: f64, row: &Row) -> Option<f64> {
+        if row.open >= stop {
+            Some(row.open)
+        } else if row.high >= stop {
+            Some(stop)
+        } else {
+            None
+        }
+    }
+
+    /// Price a sell stop triggers at: the open if the bar gaps below the stop, else the stop
+    fn sell_stop_trigger(stop: f64, row: &Row) -> Option<f64> {
+        if row.open <= stop {
+            Some(row.open)
+        } else if row.low <= stop {
+            Some(stop)
+        } else {
+            None
+        }
+    }
+}
+
+/// A single fill of an order
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Execution {
+    pub timestamp: i64,
+    pub price: f64,
+    pub size: i64,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -135,7 +569,7 @@ pub enum OrderDistance {
 
 impl OrderDistance {
     /// Calculate the order price given current price and side
-    pub fn calculate(&self, current_price: f64, order_type: OrderType, _atr: Option<f64>) -> Result<f64, OrderError> {
+    pub fn calculate(&self, current_price: f64, order_type: OrderType, atr: Option<f64>) -> Result<f64, OrderError> {
         let is_buy = order_type.is_buy();
         match self {
             OrderDistance::Fixed(price) => Ok(*price),
@@ -154,7 +588,7 @@ impl OrderDistance {
                 }
             }
             OrderDistance::ATR(multiple) => {
-                if let Some(atr) = _atr {
+                if let Some(atr) = atr {
                     if is_buy {
                         Ok(current_price + (atr * multiple))
                     } else {
@@ -179,8 +613,77 @@ pub enum OrderType {
     StopLimitBuy(OrderDistance, OrderDistance), // stop price, limit price, distance
     StopMarketSell(OrderDistance),  // stop price, distance
     StopLimitSell(OrderDistance, OrderDistance), // stop price, limit price, distance
-    AuctionOpen(),
-    AuctionClose(),
+    /// Stop-market whose stop trails the lowest price since submission by the distance
+    TrailingStopMarketBuy(OrderDistance),
+    /// Stop-market whose stop trails the highest price since submission by the distance
+    TrailingStopMarketSell(OrderDistance),
+    /// Trailing stop (first distance) that becomes a limit the second distance from its trigger
+    TrailingStopLimitBuy(OrderDistance, OrderDistance),
+    /// Trailing stop (first distance) that becomes a limit the second distance from its trigger
+    TrailingStopLimitSell(OrderDistance, OrderDistance),
+    /// Market-on-open, fills at the opening auction
+    AuctionOpen(OrderSide),
+    /// Market-on-close, fills at the closing auction
+    AuctionClose(OrderSide),
+}
+
+/// Orders a strategy submits together, linked so that a fill of one affects the others
+#[derive(Debug, Clone)]
+pub enum OrderGroup {
+    /// One-cancels-other: the first order to fill cancels the rest
+    OCO(Vec<OrderType>),
+    /// One-triggers-other: `then` is submitted once `entry` has filled
+    OTO { entry: OrderType, then: Vec<OrderType> },
+    /// An entry whose fill submits a stop-loss and take-profit as an OCO pair
+    Bracket { entry: OrderType, stop_loss: OrderType, take_profit: OrderType },
+}
+
+/// Stable id of an order, handed back to the strategy that submitted it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct OrderId(pub u64);
+
+impl std::fmt::Display for OrderId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Changes to a working order; unset fields are kept
+#[derive(Debug, Clone, Default)]
+pub struct OrderReplace {
+    /// New type and prices, on the same side
+    pub order_type: Option<OrderType>,
+    /// New total size, including what has filled
+    pub size: Option<i64>,
+    pub good_until: Option<OrderTimeline>,
+}
+
+impl OrderReplace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_order_type(mut self, order_type: OrderType) -> Self {
+        self.order_type = Some(order_type);
+        self
+    }
+
+    pub fn with_size(mut self, size: i64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_good_until(mut self, good_until: OrderTimeline) -> Self {
+        self.good_until = Some(good_until);
+        self
+    }
+}
+
+/// Direction of orders whose type doesn't imply one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Buy,
+    Sell,
 }
 
 impl OrderType {    
@@ -190,7 +693,11 @@ impl OrderType {
             OrderType::MarketBuy() | 
             OrderType::LimitBuy(_) | 
             OrderType::StopMarketBuy(_) | 
-            OrderType::StopLimitBuy(_, _)
+            OrderType::StopLimitBuy(_, _) |
+            OrderType::TrailingStopMarketBuy(_) |
+            OrderType::TrailingStopLimitBuy(_, _) |
+            OrderType::AuctionOpen(OrderSide::Buy) |
+            OrderType::AuctionClose(OrderSide::Buy)
         )
     }
     
@@ -200,27 +707,61 @@ impl OrderType {
             OrderType::MarketSell() | 
             OrderType::LimitSell(_) | 
             OrderType::StopMarketSell(_) | 
-            OrderType::StopLimitSell(_, _)
+            OrderType::StopLimitSell(_, _) |
+            OrderType::TrailingStopMarketSell(_) |
+            OrderType::TrailingStopLimitSell(_, _) |
+            OrderType::AuctionOpen(OrderSide::Sell) |
+            OrderType::AuctionClose(OrderSide::Sell)
         )
     }
     
+    /// Check if this order fills as a market order once triggered (market and stop-market)
+    pub fn is_marketable(&self) -> bool {
+        matches!(self,
+            OrderType::MarketBuy() |
+            OrderType::MarketSell() |
+            OrderType::StopMarketBuy(_) |
+            OrderType::StopMarketSell(_) |
+            OrderType::TrailingStopMarketBuy(_) |
+            OrderType::TrailingStopMarketSell(_)
+        )
+    }
+
+    /// Check if this is a market-on-open or market-on-close order
+    pub fn is_auction(&self) -> bool {
+        matches!(self, OrderType::AuctionOpen(_) | OrderType::AuctionClose(_))
+    }
+
     /// Validate that stop/limit prices are in correct order
     /// For StopLimitBuy: stop_price <= limit_price
     /// For StopLimitSell: stop_price >= limit_price
-    pub fn validate(&self, current_price: f64) -> Result<(), OrderError> {
+    /// Trails must be a positive relative distance, not a fixed price
+    /// Without an ATR, the order of ATR-based prices can't be checked and is accepted
+    pub fn validate(&self, current_price: f64, atr: Option<f64>) -> Result<(), OrderError> {
         match self {
+            OrderType::TrailingStopMarketBuy(trail)
+            | OrderType::TrailingStopMarketSell(trail)
+            | OrderType::TrailingStopLimitBuy(trail, _)
+            | OrderType::TrailingStopLimitSell(trail, _) => match trail {
+                OrderDistance::Percent(d) | OrderDistance::Points(d) | OrderDistance::ATR(d) if *d > 0.0 => {}
+                _ => return Err(OrderError::InvalidOrder),
+            },
             OrderType::StopLimitBuy(stop_dist, limit_dist) => {
-                let stop = stop_dist.calculate(current_price, *self, None)?;
-                let limit = limit_dist.calculate(current_price, *self, None)?;
-                if stop > limit {
-                    return Err(OrderError::InvalidOrder);
+                let stop = stop_dist.calculate(current_price, *self, atr);
+                let limit = limit_dist.calculate(current_price, *self, atr);
+                if let (Ok(stop), Ok(limit)) = (stop, limit) {
+                    if stop > limit {
+                        return Err(OrderError::InvalidOrder);
+                    }
                 }
             }
             OrderType::StopLimitSell(stop_dist, limit_dist) => {
-                let stop = stop_dist.calculate(current_price, *self, None)?;
-                let limit = limit_dist.calculate(current_price, *self, None)?;
-                if stop < limit {
-                    return Err(OrderError::InvalidOrder);
+                let stop = stop_dist.calculate(current_price, *self, atr);
+                let limit = limit_dist.calculate(current_price, *self, atr);
+                if let (Ok(stop), Ok(limit)) = (stop, limit) {
+                    if stop < limit {
+                        return Err(OrderError::InvalidOrder);
+                    }
                 }
             }
             _ => {}
@@ -230,11 +771,53 @@ impl OrderType {
 }
 
 
-#[derive(Debug, Clone, Copy)]
+/// Time in force
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OrderTimeline {
     GTC, // good til cancelled
     EOD, // end of day
-    // GTD, good til date (not yet implemented)
+    /// Good til the given timestamp
+    GTD(i64),
+    /// Immediate or cancel: fill what the first checked bar allows, cancel the rest
+    IOC,
+    /// Fill or kill: fill in full on the first checked bar or cancel
+    FOK,
+    /// Good for this many checked bars
+    Bars(usize),
+}
+
+/// Why an order stopped working before it filled in full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CancelReason {
+    /// Its time in force ran out (EOD, GTD or Bars)
+    Expired,
+    /// An IOC or FOK order could not fill (in full, for FOK) on its bar
+    NotFilled,
+    /// A market-on-close order saw no regular-session bar before the close
+    AuctionMissed,
+    /// A sibling in its OCO group filled first
+    OcoSibling,
+    /// The strategy cancelled it
+    Requested,
+    /// Its prices could not be worked out, e.g. an ATR distance without an ATR
+    Invalid,
+    /// It filled but could not be applied, e.g. for lack of buying power or a position to close
+    NotExecuted,
+}
+
+impl std::fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            CancelReason::Expired => "expired",
+            CancelReason::NotFilled => "not filled in time",
+            CancelReason::AuctionMissed => "missed its auction",
+            CancelReason::OcoSibling => "cancelled by a filled OCO sibling",
+            CancelReason::Requested => "cancelled by the strategy",
+            CancelReason::Invalid => "could not be priced",
+            CancelReason::NotExecuted => "could not be executed",
+        };
+        f.write_str(reason)
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -243,8 +826,197 @@ pub enum OrderError {
     ATRRequired,
     #[error("Order is already completed")]
     AlreadyCompleted,
-    #[error("Order is already filled")]
-    AlreadyFilled,
     #[error("Order is not valid")]
     InvalidOrder,
-}
\ No newline at end of file
+    #[error("Replacement must keep the order's side")]
+    SideChanged,
+    #[error("Replacement size must be above the filled size")]
+    SizeBelowFilled,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(open: f64, high: f64, low: f64, close: f64) -> Row {
+        Row { ticker: "TEST".into(), timestamp: 0, open, high, low, close, volume: 1000 }
+    }
+
+    fn check(order_type: OrderType, row: &Row, execution: &ExecutionConfig) -> Option<f64> {
+        let mut order = Order::new(order_type, OrderAction::Open, 0, Some(OrderTimeline::GTC), 100).unwrap();
+        order.check(row, &MarketHours::default(), execution).unwrap().map(|fill| fill.price)
+    }
+
+    #[test]
+    fn test_limit_fills_at_limit_or_gapped_open() {
+        let execution = ExecutionConfig::default();
+        let buy = OrderType::LimitBuy(OrderDistance::Fixed(99.0));
+        assert_eq!(check(buy, &bar(100.0, 101.0, 98.0, 100.0), &execution), Some(99.0));
+        assert_eq!(check(buy, &bar(97.0, 98.0, 96.0, 97.5), &execution), Some(97.0));
+        assert_eq!(check(buy, &bar(100.0, 101.0, 99.5, 100.0), &execution), None);
+
+        let sell = OrderType::LimitSell(OrderDistance::Fixed(101.0));
+        assert_eq!(check(sell, &bar(100.0, 102.0, 99.0, 100.0), &execution), Some(101.0));
+        assert_eq!(check(sell, &bar(103.0, 104.0, 102.0, 103.0), &execution), Some(103.0));
+    }
+
+    #[test]
+    fn test_stop_fills_at_stop_or_gapped_open() {
+        let execution = ExecutionConfig::default();
+        let stop = OrderType::StopMarketSell(OrderDistance::Fixed(95.0));
+        assert_eq!(check(stop, &bar(100.0, 101.0, 90.0, 92.0), &execution), Some(95.0));
+        assert_eq!(check(stop, &bar(90.0, 91.0, 88.0, 89.0), &execution), Some(90.0));
+
+        // Triggered at the gapped open above the limit, then waits for the limit
+        let stop_limit = OrderType::StopLimitBuy(OrderDistance::Fixed(105.0), OrderDistance::Fixed(106.0));
+        let mut order = Order::new(stop_limit, OrderAction::Open, 0, Some(OrderTimeline::GTC), 100).unwrap();
+        order.check(&bar(108.0, 109.0, 107.0, 108.0), &MarketHours::default(), &execution).unwrap();
+        assert!(order.triggered && order.fill_price.is_none());
+        order.check(&bar(107.0, 107.5, 105.5, 106.0), &MarketHours::default(), &execution).unwrap();
+        assert_eq!(order.fill_price, Some(106.0));
+    }
+
+    #[test]
+    fn test_trade_through_requires_a_tick_past_the_limit() {
+        let execution = ExecutionConfig { trade_through: true, ..ExecutionConfig::default() };
+        let buy = OrderType::LimitBuy(OrderDistance::Fixed(99.0));
+        assert_eq!(check(buy, &bar(100.0, 101.0, 99.0, 100.0), &execution), None);
+        assert_eq!(check(buy, &bar(100.0, 101.0, 98.98, 100.0), &execution), Some(99.0));
+    }
+
+    #[test]
+    fn test_participation_cap_splits_fills_across_bars() {
+        let execution = ExecutionConfig { max_participation: Some(0.1), ..ExecutionConfig::default() };
+        let mut order = Order::new(OrderType::MarketBuy(), OrderAction::Open, 0, Some(OrderTimeline::GTC), 250).unwrap();
+        let mut row = bar(10.0, 11.0, 9.0, 10.0);
+        assert_eq!(order.check(&row, &MarketHours::default(), &execution).unwrap().map(|fill| fill.size), Some(100));
+        row.close = 12.0;
+        assert_eq!(order.check(&row, &MarketHours::default(), &execution).unwrap().map(|fill| fill.size), Some(100));
+        assert!(!order.completed);
+        assert_eq!(order.check(&row, &MarketHours::default(), &execution).unwrap().map(|fill| fill.size), Some(50));
+        assert!(order.completed);
+        assert_eq!(order.executions.len(), 3);
+        assert!((order.fill_price.unwrap() - 11.2).abs() < 1e-9);
+    }
+
+    /// Nanosecond timestamp of 2021-03-01 + `day` days at `hour:minute`
+    fn at(day: u64, hour: u32, minute: u32) -> i64 {
+        let date = chrono::NaiveDate::from_ymd_opt(2021, 3, 1).unwrap() + chrono::Days::new(day);
+        date.and_hms_opt(hour, minute, 0).unwrap().and_utc().timestamp_nanos_opt().unwrap()
+    }
+
+    #[test]
+    fn test_market_on_open_fills_at_next_session_open() {
+        let execution = ExecutionConfig::default();
+        let hours = MarketHours { include_premarket: true, ..MarketHours::default() };
+        let moo = OrderType::AuctionOpen(OrderSide::Buy);
+        assert!(execution.auction.check_submission(&moo, at(0, 9, 29), &hours).is_err());
+        assert!(execution.auction.check_submission(&moo, at(0, 12, 0), &hours).is_ok());
+
+        // Submitted during the session, so it waits for tomorrow's open
+        let mut order = Order::new(moo, OrderAction::Open, at(0, 12, 0), None, 100).unwrap();
+        for timestamp in [at(0, 12, 5), at(0, 15, 55), at(1, 9, 0)] {
+            let row = Row { timestamp, ..bar(100.0, 101.0, 99.0, 100.0) };
+            assert_eq!(order.check(&row, &hours, &execution).unwrap(), None);
+        }
+        let row = Row { timestamp: at(1, 9, 30), ..bar(102.0, 103.0, 101.0, 102.5) };
+        assert_eq!(order.check(&row, &hours, &execution).unwrap().map(|fill| fill.price), Some(102.0));
+    }
+
+    #[test]
+    fn test_market_on_close_fills_at_last_session_close() {
+        let execution = ExecutionConfig::default();
+        let hours = MarketHours::default();
+        let moc = OrderType::AuctionClose(OrderSide::Sell);
+        assert!(execution.auction.check_submission(&moc, at(0, 15, 55), &hours).is_err());
+
+        let mut order = Order::new(moc, OrderAction::Close, at(0, 15, 0), None, 100).unwrap();
+        for (timestamp, close) in [(at(0, 15, 5), 100.0), (at(0, 15, 55), 101.0)] {
+            let row = Row { timestamp, ..bar(100.0, 102.0, 99.0, close) };
+            assert_eq!(order.check(&row, &hours, &execution).unwrap(), None);
+        }
+        // The next day's first bar shows 15:55 was the last bar of the session
+        let row = Row { timestamp: at(1, 9, 30), ..bar(98.0, 99.0, 97.0, 98.0) };
+        let fill = order.check(&row, &hours, &execution).unwrap().unwrap();
+        assert_eq!((fill.timestamp, fill.price), (at(0, 15, 55), 101.0));
+    }
+
+    #[test]
+    fn test_time_in_force() {
+        let hours = MarketHours::default();
+        let execution = ExecutionConfig { max_participation: Some(0.1), ..ExecutionConfig::default() };
+        let limit = OrderType::LimitBuy(OrderDistance::Fixed(90.0));
+        let row = |timestamp| Row { timestamp, ..bar(100.0, 101.0, 99.0, 100.0) };
+
+        let mut gtd = Order::new(limit, OrderAction::Open, 0, Some(OrderTimeline::GTD(10)), 100).unwrap();
+        assert_eq!(gtd.check(&row(10), &hours, &execution).unwrap(), None);
+        assert!(!gtd.completed);
+        gtd.check(&row(11), &hours, &execution).unwrap();
+        assert_eq!(gtd.cancelled, Some(CancelReason::Expired));
+
+        let mut bars = Order::new(limit, OrderAction::Open, 0, Some(OrderTimeline::Bars(2)), 100).unwrap();
+        for timestamp in 1..=3 {
+            bars.check(&row(timestamp), &hours, &execution).unwrap();
+        }
+        assert_eq!((bars.bars_checked, bars.cancelled), (2, Some(CancelReason::Expired)));
+
+        // 10% of 1000 shares can fill: IOC keeps 100 and cancels 150, FOK fills nothing
+        let mut ioc = Order::new(OrderType::MarketBuy(), OrderAction::Open, 0, Some(OrderTimeline::IOC), 250).unwrap();
+        assert_eq!(ioc.check(&row(1), &hours, &execution).unwrap().map(|fill| fill.size), Some(100));
+        assert_eq!((ioc.completed, ioc.cancelled), (true, Some(CancelReason::NotFilled)));
+
+        let mut fok = Order::new(OrderType::MarketBuy(), OrderAction::Open, 0, Some(OrderTimeline::FOK), 250).unwrap();
+        assert_eq!(fok.check(&row(1), &hours, &execution).unwrap(), None);
+        assert_eq!((fok.fill_size, fok.cancelled), (0, Some(CancelReason::NotFilled)));
+    }
+
+    #[test]
+    fn test_trailing_stop_ratchets_with_the_high() {
+        let execution = ExecutionConfig::default();
+        let hours = MarketHours::default();
+        assert!(Order::new(OrderType::TrailingStopMarketSell(OrderDistance::Fixed(98.0)), OrderAction::Close, 0, None, 100).is_err());
+
+        let trail = OrderType::TrailingStopMarketSell(OrderDistance::Points(2.0));
+        let mut order = Order::new(trail, OrderAction::Close, 0, Some(OrderTimeline::GTC), 100).unwrap();
+        // Low first, so the 99.5 low is checked against the 98 stop before the 103 high moves it
+        assert_eq!(order.check(&bar(100.0, 103.0, 99.5, 102.0), &hours, &execution).unwrap(), None);
+        assert_eq!((order.trail_extreme, order.trigger_price), (Some(103.0), Some(101.0)));
+        let fill = order.check(&bar(102.0, 102.5, 100.0, 100.5), &hours, &execution).unwrap();
+        assert_eq!(fill.map(|fill| fill.price), Some(101.0));
+
+        // A trailing stop-limit that gaps through its limit rests there
+        let trail = OrderType::TrailingStopLimitSell(OrderDistance::Points(2.0), OrderDistance::Points(0.5));
+        let mut order = Order::new(trail, OrderAction::Close, 0, Some(OrderTimeline::GTC), 100).unwrap();
+        assert_eq!(order.check(&bar(100.0, 103.0, 99.5, 102.0), &hours, &execution).unwrap(), None);
+        assert_eq!(order.check(&bar(99.0, 99.5, 98.0, 98.5), &hours, &execution).unwrap(), None);
+        assert!(order.triggered);
+        let fill = order.check(&bar(100.0, 101.0, 99.0, 100.8), &hours, &execution).unwrap();
+        assert_eq!(fill.map(|fill| fill.price), Some(100.5));
+    }
+
+    #[test]
+    fn test_prices_resolve_at_submission() {
+        let execution = ExecutionConfig::default();
+        let hours = MarketHours::default();
+        let limit = OrderType::LimitBuy(OrderDistance::Percent(-1.0));
+        let mut order = Order::new(limit, OrderAction::Open, 0, Some(OrderTimeline::GTC), 100).unwrap();
+        order.resolve_prices(100.0, None).unwrap();
+        assert_eq!(order.limit_price, Some(99.0));
+        // Rests at 99 while the market drifts down to it
+        assert_eq!(order.check(&bar(99.5, 99.8, 99.2, 99.3), &hours, &execution).unwrap(), None);
+        let fill = order.check(&bar(99.3, 99.4, 98.9, 98.95), &hours, &execution).unwrap();
+        assert_eq!(fill.map(|fill| fill.price), Some(99.0));
+
+        // Re-pegged orders follow each bar's close
+        let mut order = Order::new(limit, OrderAction::Open, 0, Some(OrderTimeline::GTC), 100).unwrap();
+        order.repeg = true;
+        order.resolve_prices(100.0, None).unwrap();
+        assert_eq!(order.check(&bar(101.0, 102.5, 100.5, 102.0), &hours, &execution).unwrap(), None);
+        assert!((order.limit_price.unwrap() - 100.98).abs() < 1e-9);
+
+        let stop = OrderType::StopMarketSell(OrderDistance::ATR(2.0));
+        let mut order = Order::new(stop, OrderAction::Close, 0, None, 100).unwrap();
+        assert!(matches!(order.resolve_prices(100.0, None), Err(OrderError::ATRRequired)));
+        order.resolve_prices(100.0, Some(1.5)).unwrap();
+        assert_eq!(order.stop_price, Some(97.0));
+    }
+}
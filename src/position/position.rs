@@ -45,7 +45,11 @@ pub struct Position {
     /// Exit timestamp (None if position is still open)
     pub exit_timestamp: Option<i64>,
     /// Current state
-    pub state: PositionState
+    pub state: PositionState,
+    /// Commissions and fees paid on entry and exit
+    pub fees: f64,
+    /// Borrow fees accrued while short
+    pub borrow_fees: f64,
 }
 
 impl Position {
@@ -68,6 +72,8 @@ impl Position {
             exit_price: None,
             exit_timestamp: None,
             state: PositionState::Open,
+            fees: 0.0,
+            borrow_fees: 0.0,
         }
     }
     
@@ -95,6 +101,11 @@ impl Position {
         })
     }
     
+    /// Profit/loss after commissions, fees and borrow costs
+    pub fn net_pnl(&self) -> Option<f64> {
+        self.pnl().map(|pnl| pnl - self.fees - self.borrow_fees)
+    }
+    
     /// Calculate profit/loss percentage
     pub fn pnl_percent(&self) -> Option<f64> {
         self.exit_price.map(|exit_price| {
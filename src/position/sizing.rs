@@ -1,8 +1,7 @@
-use crate::config::Config;
-use crate::types::ohlcv::Row;
 use crate::backtest::signal::Signal;
+use serde::Deserialize;
 /// Strategy for determining position size
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Deserialize)]
 pub enum SizingStrategy {
     /// Fixed number of shares
     Fixed(i64),
@@ -13,6 +12,7 @@ pub enum SizingStrategy {
     /// Risk-based sizing (risk % of account, requires stop loss)
     RiskBased { risk_percent: f64, stop_distance: f64 },
     /// signal based, pass function that takes in signal and outputs f64
+    #[serde(skip)]
     SignalBased(fn(Signal) -> f64),
 }
 
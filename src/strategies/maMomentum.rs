@@ -21,6 +21,12 @@ impl MaMomentumStrategy {
     }
 }
 
+impl Default for MaMomentumStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Strategy for MaMomentumStrategy {
     fn name(&self) -> &str {
         "MA Momentum"
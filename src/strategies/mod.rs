@@ -1,2 +1,20 @@
+#[allow(non_snake_case)]
 pub mod maMomentum;
 
+use crate::strategy::Strategy;
+
+/// Builds a fresh strategy instance; the engine calls it once per ticker
+pub type StrategyFactory = fn() -> Box<dyn Strategy>;
+
+/// Strategies that can be selected by name, e.g. from the command line
+pub const STRATEGIES: &[(&str, StrategyFactory)] = &[
+    ("ma_momentum", maMomentum::create_ma_momentum),
+];
+
+/// Look up a strategy factory by its registered name
+pub fn find_strategy(name: &str) -> Option<StrategyFactory> {
+    STRATEGIES
+        .iter()
+        .find(|(registered, _)| *registered == name)
+        .map(|(_, factory)| *factory)
+}
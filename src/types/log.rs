@@ -1,7 +1,30 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use polars::prelude::*;
 use serde::Serialize;
 use crate::position::strategy::Action;
+use crate::position::side::Side;
 use crate::position::position::Position;
+use crate::backtest::fees::FeeBreakdown;
+use crate::position::order::Execution;
+
+/// Errors that can occur while exporting trade logs
+#[derive(Debug, thiserror::Error)]
+pub enum LogError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Polars(#[from] PolarsError),
+}
+
+/// Prefix of the columns an indicator map is expanded into, e.g. `ind_fast_ma`
+pub const INDICATOR_PREFIX: &str = "ind_";
 
 #[derive(Serialize)]
 pub struct TradeLog {
@@ -10,8 +33,17 @@ pub struct TradeLog {
     pub action: Action,
     pub strategy_name: String,
     pub indicator_values: HashMap<String, f64>,
+    /// Realized PnL net of the position's fees and borrow costs (0 for entries)
     pub pnl: f64,
     pub condition_name: String, // the name of the PositionStrategy that triggered the action
+    /// Dollar cost of slippage on this fill, already included in the fill price
+    pub slippage: f64,
+    /// Dollar cost of market impact on this fill, already included in the fill price
+    pub impact: f64,
+    /// Commission and regulatory fees charged on this fill
+    pub fees: FeeBreakdown,
+    /// The fill this log records; smaller than the position when an order filled in parts
+    pub execution: Execution,
 }
 
 impl TradeLog {
@@ -22,7 +54,11 @@ impl TradeLog {
         condition_name: String,
         indicator_values: HashMap<String, f64>,
     ) -> Self {
-        let pnl = position.pnl().unwrap_or(0.0);
+        let pnl = position.net_pnl().unwrap_or(0.0);
+        let execution = match (action, position.exit_price, position.exit_timestamp) {
+            (Action::Exit, Some(price), Some(timestamp)) => Execution { timestamp, price, size: position.size },
+            _ => Execution { timestamp: position.entry_timestamp, price: position.entry_price, size: position.size },
+        };
         Self {
             position,
             action,
@@ -30,6 +66,235 @@ impl TradeLog {
             indicator_values,
             pnl,
             condition_name,
+            slippage: 0.0,
+            impact: 0.0,
+            fees: FeeBreakdown::default(),
+            execution,
         }
     }
+
+    pub fn with_execution(mut self, execution: Execution) -> Self {
+        self.execution = execution;
+        self
+    }
+
+    pub fn with_slippage(mut self, slippage: f64) -> Self {
+        self.slippage = slippage;
+        self
+    }
+
+    pub fn with_impact(mut self, impact: f64) -> Self {
+        self.impact = impact;
+        self
+    }
+
+    pub fn with_fees(mut self, fees: FeeBreakdown) -> Self {
+        self.fees = fees;
+        self
+    }
+}
+
+/// Flat export row for a TradeLog, without the indicator values
+#[derive(Serialize)]
+struct TradeRecord<'a> {
+    position_id: &'a str,
+    ticker: &'a str,
+    side: &'a Side,
+    action: Action,
+    size: i64,
+    entry_price: f64,
+    entry_timestamp: i64,
+    exit_price: Option<f64>,
+    exit_timestamp: Option<i64>,
+    fill_timestamp: i64,
+    fill_price: f64,
+    fill_size: i64,
+    pnl: f64,
+    slippage: f64,
+    impact: f64,
+    commission: f64,
+    sec_fee: f64,
+    finra_taf: f64,
+    borrow_fees: f64,
+    strategy_name: &'a str,
+    condition_name: &'a str,
+}
+
+const RECORD_COLUMNS: [&str; 21] = [
+    "position_id", "ticker", "side", "action", "size", "entry_price", "entry_timestamp",
+    "exit_price", "exit_timestamp", "fill_timestamp", "fill_price", "fill_size", "pnl",
+    "slippage", "impact", "commission", "sec_fee", "finra_taf", "borrow_fees", "strategy_name",
+    "condition_name",
+];
+
+impl<'a> From<&'a TradeLog> for TradeRecord<'a> {
+    fn from(log: &'a TradeLog) -> Self {
+        Self {
+            position_id: &log.position.id,
+            ticker: &log.position.ticker,
+            side: &log.position.side,
+            action: log.action,
+            size: log.position.size,
+            entry_price: log.position.entry_price,
+            entry_timestamp: log.position.entry_timestamp,
+            exit_price: log.position.exit_price,
+            exit_timestamp: log.position.exit_timestamp,
+            fill_timestamp: log.execution.timestamp,
+            fill_price: log.execution.price,
+            fill_size: log.execution.size,
+            pnl: log.pnl,
+            slippage: log.slippage,
+            impact: log.impact,
+            commission: log.fees.commission,
+            sec_fee: log.fees.sec_fee,
+            finra_taf: log.fees.finra_taf,
+            borrow_fees: log.position.borrow_fees,
+            strategy_name: &log.strategy_name,
+            condition_name: &log.condition_name,
+        }
+    }
+}
+
+/// Sorted union of the indicator names in all logs
+///
+/// Every exported row gets one column per name (empty/null where a log has no
+/// value), so files from runs with the same strategy line up column for column.
+pub fn indicator_columns(logs: &[TradeLog]) -> Vec<String> {
+    let names: BTreeSet<&String> = logs.iter().flat_map(|log| log.indicator_values.keys()).collect();
+    names.into_iter().cloned().collect()
+}
+
+/// Write trade logs to a CSV file, one row per entry/exit
+pub fn write_csv(logs: &[TradeLog], path: &Path) -> Result<(), LogError> {
+    let indicators = indicator_columns(logs);
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_path(path)?;
+
+    let mut header: Vec<String> = RECORD_COLUMNS.iter().map(|c| c.to_string()).collect();
+    header.extend(indicators.iter().map(|name| format!("{}{}", INDICATOR_PREFIX, name)));
+    writer.write_record(&header)?;
+
+    for log in logs {
+        let values: Vec<Option<f64>> = indicators.iter().map(|name| log.indicator_values.get(name).copied()).collect();
+        writer.serialize((TradeRecord::from(log), values))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write trade logs as JSON Lines, one object per entry/exit
+pub fn write_jsonl(logs: &[TradeLog], path: &Path) -> Result<(), LogError> {
+    #[derive(Serialize)]
+    struct JsonRecord<'a> {
+        #[serde(flatten)]
+        record: TradeRecord<'a>,
+        #[serde(flatten)]
+        indicators: BTreeMap<String, Option<f64>>,
+    }
+
+    let indicators = indicator_columns(logs);
+    let mut writer = BufWriter::new(File::create(path)?);
+    for log in logs {
+        let record = JsonRecord {
+            record: TradeRecord::from(log),
+            indicators: indicators
+                .iter()
+                .map(|name| (format!("{}{}", INDICATOR_PREFIX, name), log.indicator_values.get(name).copied()))
+                .collect(),
+        };
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Trade logs as a DataFrame, with timestamps as nanosecond Datetimes
+pub fn to_dataframe(logs: &[TradeLog]) -> PolarsResult<DataFrame> {
+    let records: Vec<TradeRecord> = logs.iter().map(TradeRecord::from).collect();
+    let datetime = DataType::Datetime(TimeUnit::Nanoseconds, None);
+
+    let mut columns: Vec<Column> = vec![
+        Column::new("position_id".into(), records.iter().map(|r| r.position_id).collect::<Vec<_>>()),
+        Column::new("ticker".into(), records.iter().map(|r| r.ticker).collect::<Vec<_>>()),
+        Column::new("side".into(), records.iter().map(|r| Side::to_string(r.side)).collect::<Vec<_>>()),
+        Column::new("action".into(), records.iter().map(|r| format!("{:?}", r.action)).collect::<Vec<_>>()),
+        Column::new("size".into(), records.iter().map(|r| r.size).collect::<Vec<_>>()),
+        Column::new("entry_price".into(), records.iter().map(|r| r.entry_price).collect::<Vec<_>>()),
+        Column::new("entry_timestamp".into(), records.iter().map(|r| r.entry_timestamp).collect::<Vec<_>>())
+            .cast(&datetime)?,
+        Column::new("exit_price".into(), records.iter().map(|r| r.exit_price).collect::<Vec<_>>()),
+        Column::new("exit_timestamp".into(), records.iter().map(|r| r.exit_timestamp).collect::<Vec<_>>())
+            .cast(&datetime)?,
+        Column::new("fill_timestamp".into(), records.iter().map(|r| r.fill_timestamp).collect::<Vec<_>>())
+            .cast(&datetime)?,
+        Column::new("fill_price".into(), records.iter().map(|r| r.fill_price).collect::<Vec<_>>()),
+        Column::new("fill_size".into(), records.iter().map(|r| r.fill_size).collect::<Vec<_>>()),
+        Column::new("pnl".into(), records.iter().map(|r| r.pnl).collect::<Vec<_>>()),
+        Column::new("slippage".into(), records.iter().map(|r| r.slippage).collect::<Vec<_>>()),
+        Column::new("impact".into(), records.iter().map(|r| r.impact).collect::<Vec<_>>()),
+        Column::new("commission".into(), records.iter().map(|r| r.commission).collect::<Vec<_>>()),
+        Column::new("sec_fee".into(), records.iter().map(|r| r.sec_fee).collect::<Vec<_>>()),
+        Column::new("finra_taf".into(), records.iter().map(|r| r.finra_taf).collect::<Vec<_>>()),
+        Column::new("borrow_fees".into(), records.iter().map(|r| r.borrow_fees).collect::<Vec<_>>()),
+        Column::new("strategy_name".into(), records.iter().map(|r| r.strategy_name).collect::<Vec<_>>()),
+        Column::new("condition_name".into(), records.iter().map(|r| r.condition_name).collect::<Vec<_>>()),
+    ];
+    for name in indicator_columns(logs) {
+        let values: Vec<Option<f64>> = logs.iter().map(|log| log.indicator_values.get(&name).copied()).collect();
+        columns.push(Column::new(format!("{}{}", INDICATOR_PREFIX, name).into(), values));
+    }
+    DataFrame::new(columns)
+}
+
+/// Write trade logs to a Parquet file, see `to_dataframe`
+pub fn write_parquet(logs: &[TradeLog], path: &Path) -> Result<(), LogError> {
+    let mut df = to_dataframe(logs)?;
+    ParquetWriter::new(File::create(path)?).finish(&mut df)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_logs() -> Vec<TradeLog> {
+        let pos = Position::new("a".into(), "AAPL".into(), Side::Long, 10, 100.0, 1_000);
+        let mut closed = pos.clone();
+        closed.close(101.0, 2_000).unwrap();
+        vec![
+            TradeLog::new(pos, Action::Entry, "s".into(), "entry".into(), HashMap::from([("rsi".to_string(), 30.0)])),
+            TradeLog::new(closed, Action::Exit, "s".into(), "exit".into(), HashMap::from([("atr".to_string(), 1.5)])),
+        ]
+    }
+
+    #[test]
+    fn test_exports_expand_indicator_columns() {
+        let logs = sample_logs();
+        assert_eq!(indicator_columns(&logs), vec!["atr", "rsi"]);
+
+        let dir = std::env::temp_dir().join(format!("log_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        write_csv(&logs, &dir.join("trades.csv")).unwrap();
+        let csv = std::fs::read_to_string(dir.join("trades.csv")).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[0].ends_with("condition_name,ind_atr,ind_rsi"));
+        assert!(lines[1].ends_with(",entry,,30.0"));
+        assert!(lines[2].ends_with(",exit,1.5,"));
+
+        write_jsonl(&logs, &dir.join("trades.jsonl")).unwrap();
+        let jsonl = std::fs::read_to_string(dir.join("trades.jsonl")).unwrap();
+        let first: serde_json::Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+        assert_eq!(first["ind_rsi"], 30.0);
+        assert!(first["ind_atr"].is_null());
+        assert!(first["exit_price"].is_null());
+
+        write_parquet(&logs, &dir.join("trades.parquet")).unwrap();
+        let df = crate::parsing::parquet::read_parquet(&dir.join("trades.parquet")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(df.shape(), (2, 23));
+        assert_eq!(df.column("ind_atr").unwrap().null_count(), 1);
+        assert!(matches!(df.column("entry_timestamp").unwrap().dtype(), DataType::Datetime(TimeUnit::Nanoseconds, _)));
+    }
 }